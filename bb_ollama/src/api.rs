@@ -1,30 +1,511 @@
-use std::time::Duration;
+use std::{
+    io::{BufRead, BufReader},
+    time::Duration,
+};
 
-use eyre::{Context, Result};
-use reqwest::blocking::Client;
-use serde::{Deserialize, Serialize};
+use reqwest::{
+    blocking::{Client, RequestBuilder, Response},
+    Method, Url,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::models::{chat_request::Chat, message::Message};
+use crate::{
+    error::{ErrorBody, OllamaError},
+    models::{
+        chat_request::Chat,
+        embed::{EmbedRequest, EmbedResponse},
+        generate_request::{Generate, GenerateResponse},
+        local_model::{
+            LocalModel, ModelInfo, ModelList, ModelRequest, PullProgress, RunningModel,
+            RunningModelList,
+        },
+        message::Message,
+    },
+    retry::RetryPolicy,
+};
 
-const OLLAMA_CHAT_URL: &str = "http://localhost:11434/api/chat";
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60 * 15);
+const PULL_TIMEOUT: Duration = Duration::from_secs(60 * 60 * 24);
 
-pub fn send_to_ollama(chat: &Chat) -> Result<Message> {
-    let client = Client::new();
-    let request = client
-        .post(OLLAMA_CHAT_URL)
-        .json(chat)
-        .timeout(Duration::from_secs(60 * 15))
-        .send()
-        .context("Sending message to Ollama")?;
-    let chat_response = request
-        .json::<ChatResponse>()
-        .context("converting response from Ollama to a Chat")?;
+/// Owns everything needed to talk to an Ollama server. The underlying HTTP client is reused
+/// between requests, so clone this rather than building a new one for every chat.
+#[derive(Debug, Clone)]
+pub struct OllamaClient {
+    pub base_url: Url,
+    pub timeout: Duration,
+    pub headers: Vec<(String, String)>,
+    pub retry_policy: RetryPolicy,
+    http: Client,
+}
+
+impl OllamaClient {
+    pub fn new(base_url: Url) -> Self {
+        Self {
+            base_url,
+            timeout: DEFAULT_TIMEOUT,
+            headers: vec![],
+            retry_policy: RetryPolicy::default(),
+            http: Client::new(),
+        }
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+
+        self
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+
+        self
+    }
+
+    pub fn chat(&self, chat: &Chat) -> Result<ChatResponse, OllamaError> {
+        self.retry_policy.run(|| {
+            let response = self.send(self.post("api/chat")?.json(chat))?;
+
+            parse_json(response)
+        })
+    }
+
+    /// Sends the chat expecting Ollama to stream the reply back as newline delimited JSON. The
+    /// chat must have `stream` set to `Some(true)`, see `Chat::send_stream`. Only connecting is
+    /// retried, once chunks have been handed out a failure is returned as is.
+    pub fn chat_stream(
+        &self,
+        chat: &Chat,
+        on_chunk: impl FnMut(&Message),
+    ) -> Result<ChatResponse, OllamaError> {
+        let response = self
+            .retry_policy
+            .run(|| self.send(self.post("api/chat")?.json(chat)))?;
+
+        read_chat_stream(BufReader::new(response), on_chunk)
+    }
+
+    pub fn generate(&self, generate: &Generate) -> Result<GenerateResponse, OllamaError> {
+        self.retry_policy.run(|| {
+            let response = self.send(self.post("api/generate")?.json(generate))?;
+
+            parse_json(response)
+        })
+    }
+
+    /// Embeds every input with the given model, returning one vector per input in the same order.
+    pub fn embed(
+        &self,
+        model: &str,
+        input: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<Vec<Vec<f32>>, OllamaError> {
+        Ok(self
+            .embed_request(&EmbedRequest::new(model, input))?
+            .embeddings)
+    }
+
+    pub fn embed_request(&self, request: &EmbedRequest) -> Result<EmbedResponse, OllamaError> {
+        self.retry_policy.run(|| {
+            let response = self.send(self.post("api/embed")?.json(request))?;
+
+            parse_json(response)
+        })
+    }
+
+    /// Lists the models that have been pulled onto the server.
+    pub fn list_models(&self) -> Result<Vec<LocalModel>, OllamaError> {
+        self.retry_policy.run(|| {
+            let response = self.send(self.request(Method::GET, "api/tags")?)?;
+
+            Ok(parse_json::<ModelList>(response)?.models)
+        })
+    }
+
+    pub fn has_model(&self, name: &str) -> Result<bool, OllamaError> {
+        Ok(self.list_models()?.iter().any(|model| model.matches(name)))
+    }
+
+    pub fn show_model(&self, name: &str) -> Result<ModelInfo, OllamaError> {
+        let body = ModelRequest {
+            model: name,
+            stream: None,
+        };
+
+        self.retry_policy.run(|| {
+            let response = self.send(self.post("api/show")?.json(&body))?;
+
+            parse_json(response)
+        })
+    }
+
+    /// Downloads a model onto the server, handing every progress update to `on_progress`. Pulls
+    /// can take a very long time so they get a day instead of the client timeout.
+    pub fn pull_model(
+        &self,
+        name: &str,
+        mut on_progress: impl FnMut(&PullProgress),
+    ) -> Result<(), OllamaError> {
+        let body = ModelRequest {
+            model: name,
+            stream: Some(true),
+        };
+        let response = self.retry_policy.run(|| {
+            let request = self.post("api/pull")?.json(&body).timeout(PULL_TIMEOUT);
+
+            self.send(request)
+        })?;
+        let mut succeeded = false;
+
+        read_json_lines(BufReader::new(response), |progress: PullProgress| {
+            on_progress(&progress);
+            succeeded = progress.is_success();
+
+            succeeded
+        })?;
+
+        if !succeeded {
+            return Err(OllamaError::Ollama {
+                status: None,
+                message: format!("pulling {name} ended before it succeeded"),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn delete_model(&self, name: &str) -> Result<(), OllamaError> {
+        let body = ModelRequest {
+            model: name,
+            stream: None,
+        };
+
+        self.retry_policy.run(|| {
+            self.send(self.request(Method::DELETE, "api/delete")?.json(&body))?;
+
+            Ok(())
+        })
+    }
+
+    /// Lists the models that are currently loaded into memory.
+    pub fn running_models(&self) -> Result<Vec<RunningModel>, OllamaError> {
+        self.retry_policy.run(|| {
+            let response = self.send(self.request(Method::GET, "api/ps")?)?;
+
+            Ok(parse_json::<RunningModelList>(response)?.models)
+        })
+    }
+
+    pub fn endpoint(&self, path: &str) -> Result<Url, OllamaError> {
+        let mut url = self.base_url.clone();
+
+        url.path_segments_mut()
+            .map_err(|_| OllamaError::InvalidUrl(self.base_url.to_string()))?
+            .pop_if_empty()
+            .extend(path.split('/'));
+
+        Ok(url)
+    }
+
+    fn post(&self, path: &str) -> Result<RequestBuilder, OllamaError> {
+        self.request(Method::POST, path)
+    }
+
+    fn request(&self, method: Method, path: &str) -> Result<RequestBuilder, OllamaError> {
+        let url = self.endpoint(path)?;
+
+        Ok(self.with_headers(self.http.request(method, url)))
+    }
+
+    fn with_headers(&self, request: RequestBuilder) -> RequestBuilder {
+        self.headers
+            .iter()
+            .fold(request.timeout(self.timeout), |request, (name, value)| {
+                request.header(name, value)
+            })
+    }
+
+    /// Sends the request, turning any error status from Ollama into an `OllamaError`.
+    fn send(&self, request: RequestBuilder) -> Result<Response, OllamaError> {
+        let response = request.send()?;
+        let status = response.status();
+
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let body = response.text().unwrap_or_default();
+
+        Err(OllamaError::from_status(status.as_u16(), body))
+    }
+}
+
+impl Default for OllamaClient {
+    fn default() -> Self {
+        Self::new(Url::parse(DEFAULT_OLLAMA_URL).expect("default Ollama url is valid"))
+    }
+}
+
+fn parse_json<T: DeserializeOwned>(response: Response) -> Result<T, OllamaError> {
+    let body = response.text()?;
+
+    parse_line(&body)
+}
+
+/// Parses one JSON document from Ollama, which may be an error instead of the expected value.
+fn parse_line<T: DeserializeOwned>(line: &str) -> Result<T, OllamaError> {
+    if let Ok(ErrorBody { error }) = serde_json::from_str::<ErrorBody>(line) {
+        return Err(OllamaError::Ollama {
+            status: None,
+            message: error,
+        });
+    }
 
-    Ok(chat_response.message)
+    Ok(serde_json::from_str(line)?)
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Reads newline delimited JSON from Ollama, handing every item to `on_item` until it returns
+/// `true` to say that it has seen the last one.
+fn read_json_lines<T: DeserializeOwned>(
+    reader: impl BufRead,
+    mut on_item: impl FnMut(T) -> bool,
+) -> Result<(), OllamaError> {
+    for line in reader.lines() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if on_item(parse_line(&line)?) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the chunks of a streamed chat response, handing each partial message to `on_chunk` as it
+/// arrives, and assembles the full message once Ollama reports that it is done. The returned
+/// response carries the statistics from the final chunk.
+pub fn read_chat_stream(
+    reader: impl BufRead,
+    mut on_chunk: impl FnMut(&Message),
+) -> Result<ChatResponse, OllamaError> {
+    let mut message = Message::default();
+    let mut last_chunk = None;
+
+    read_json_lines(reader, |mut chunk: ChatResponse| {
+        on_chunk(&chunk.message);
+
+        message.role = chunk.message.role.clone();
+        message.content.push_str(&chunk.message.content);
+
+        if let Some(tool_calls) = chunk.message.tool_calls.take() {
+            message
+                .tool_calls
+                .get_or_insert_with(Vec::new)
+                .extend(tool_calls);
+        }
+
+        let done = chunk.done;
+        last_chunk = Some(chunk);
+
+        done
+    })?;
+
+    let mut response = last_chunk.ok_or_else(|| OllamaError::Ollama {
+        status: None,
+        message: "the stream ended without sending a reply".to_owned(),
+    })?;
+
+    response.message = message;
+
+    Ok(response)
+}
+
+/// A reply from the chat endpoint. Durations are reported by Ollama in nanoseconds, and the
+/// statistics are only present on the final chunk when streaming.
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct ChatResponse {
     pub model: String,
+    pub created_at: Option<String>,
     pub message: Message,
+    #[serde(default)]
+    pub done: bool,
+    pub done_reason: Option<String>,
+    pub total_duration: Option<u64>,
+    pub load_duration: Option<u64>,
+    pub prompt_eval_count: Option<u64>,
+    pub prompt_eval_duration: Option<u64>,
+    pub eval_count: Option<u64>,
+    pub eval_duration: Option<u64>,
+}
+
+impl ChatResponse {
+    /// How many tokens of the context window this turn used, the prompt plus the reply.
+    pub fn context_tokens(&self) -> u64 {
+        self.prompt_eval_count.unwrap_or_default() + self.eval_count.unwrap_or_default()
+    }
+
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        tokens_per_second(self.eval_count?, self.eval_duration?)
+    }
+}
+
+pub(crate) fn tokens_per_second(tokens: u64, duration_in_nanoseconds: u64) -> Option<f64> {
+    if duration_in_nanoseconds == 0 {
+        return None;
+    }
+
+    Some(tokens as f64 / Duration::from_nanos(duration_in_nanoseconds).as_secs_f64())
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use crate::models::message::Role;
+    #[allow(unused_imports)]
+    use eyre::Result;
+
+    #[test]
+    fn should_assemble_streamed_chunks_into_one_message() -> Result<()> {
+        let stream = r#"{"model":"llama3.2:1b","message":{"role":"assistant","content":"How can "},"done":false}
+{"model":"llama3.2:1b","message":{"role":"assistant","content":"I help?"},"done":false}
+{"model":"llama3.2:1b","message":{"role":"assistant","content":""},"done":true}
+"#;
+        let mut chunks = vec![];
+
+        let response = read_chat_stream(stream.as_bytes(), |chunk| {
+            chunks.push(chunk.content.clone())
+        })?;
+        let message = response.message;
+
+        assert_eq!(chunks, vec!["How can ", "I help?", ""]);
+        assert_eq!(message.role, Role::Assistant);
+        assert_eq!(message.content, "How can I help?");
+        assert!(message.tool_calls.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_collect_streamed_tool_calls() -> Result<()> {
+        let stream = r#"{"model":"llama3.1","message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"check_weather","arguments":{"location":"New York, NY"}}}]},"done":false}
+{"model":"llama3.1","message":{"role":"assistant","content":""},"done":true}
+"#;
+        let mut tool_call_chunks = 0;
+
+        let response = read_chat_stream(stream.as_bytes(), |chunk| {
+            if chunk.tool_calls.is_some() {
+                tool_call_chunks += 1;
+            }
+        })?;
+        let tool_calls = response.message.tool_calls.unwrap();
+
+        assert_eq!(tool_call_chunks, 1);
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].function.name, "check_weather");
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_keep_statistics_from_the_final_streamed_chunk() -> Result<()> {
+        let stream = r#"{"model":"llama3.2:1b","message":{"role":"assistant","content":"Hi"},"done":false}
+{"model":"llama3.2:1b","message":{"role":"assistant","content":""},"done":true,"done_reason":"stop","total_duration":5000000000,"load_duration":1000000,"prompt_eval_count":26,"prompt_eval_duration":130000000,"eval_count":20,"eval_duration":2000000000}
+"#;
+
+        let response = read_chat_stream(stream.as_bytes(), |_| {})?;
+
+        assert_eq!(response.message.content, "Hi");
+        assert_eq!(response.done_reason.as_deref(), Some("stop"));
+        assert_eq!(response.context_tokens(), 46);
+        assert_eq!(response.tokens_per_second(), Some(10.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_stop_at_errors_sent_in_the_middle_of_a_stream() {
+        let stream = r#"{"model":"llama3.2:1b","message":{"role":"assistant","content":"Hi"},"done":false}
+{"error":"an unexpected error occurred"}
+"#;
+
+        let error = read_chat_stream(stream.as_bytes(), |_| {}).unwrap_err();
+
+        assert!(
+            matches!(error, OllamaError::Ollama { status: None, message } if message == "an unexpected error occurred")
+        );
+    }
+
+    #[test]
+    fn should_read_pull_progress_until_success() -> Result<()> {
+        let stream = r#"{"status":"pulling manifest"}
+{"status":"pulling 6a0746a1ec1a","digest":"sha256:6a0746a1ec1a","total":200,"completed":100}
+{"status":"success"}
+{"status":"this line is never read"}
+"#;
+        let mut updates = vec![];
+
+        read_json_lines(stream.as_bytes(), |progress: PullProgress| {
+            updates.push(progress.status.clone());
+
+            progress.is_success()
+        })?;
+
+        assert_eq!(
+            updates,
+            vec!["pulling manifest", "pulling 6a0746a1ec1a", "success"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_build_endpoints_from_the_base_url() -> Result<()> {
+        let client = OllamaClient::default();
+        let remote_client = OllamaClient::new(Url::parse("http://ollama.local:8080/proxy/")?);
+
+        assert_eq!(
+            client.endpoint("api/chat")?.as_str(),
+            "http://localhost:11434/api/chat"
+        );
+        assert_eq!(
+            remote_client.endpoint("api/chat")?.as_str(),
+            "http://ollama.local:8080/proxy/api/chat"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_not_retry_a_request_that_timed_out() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}", listener.local_addr()?))?;
+        let (accepted, connections) = std::sync::mpsc::channel();
+
+        // accepts connections and never answers them
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let _ = accepted.send(stream);
+            }
+        });
+
+        let error = OllamaClient::new(url)
+            .timeout(Duration::from_millis(200))
+            .list_models()
+            .unwrap_err();
+
+        assert!(matches!(error, OllamaError::Timeout(_)), "{error:?}");
+        assert_eq!(connections.try_iter().count(), 1);
+
+        Ok(())
+    }
 }
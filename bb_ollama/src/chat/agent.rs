@@ -0,0 +1,325 @@
+use crate::{
+    api::ChatResponse,
+    error::OllamaError,
+    models::{chat_request::Chat, message::Message, tool::ToolError, tool_call::ToolCall},
+};
+
+use super::tool_registry::{ToolControl, ToolRegistry, ToolResult};
+
+type Callback<'a, T> = Box<dyn FnMut(&T) + 'a>;
+type AfterToolCall<'a> = Box<dyn FnMut(&ToolCall, &ToolResult) + 'a>;
+type StopCondition<'a> = Box<dyn FnMut(&Message) -> bool + 'a>;
+
+/// Drives a chat through its tool calls: send the chat, run every tool the model calls, add the
+/// results to the chat and send it again, until the model replies without calling a tool.
+pub struct Agent<'a> {
+    pub chat: Chat,
+    pub tools: ToolRegistry,
+    max_iterations: u32,
+    on_chunk: Option<Callback<'a, Message>>,
+    on_response: Option<Callback<'a, ChatResponse>>,
+    before_tool_call: Option<Callback<'a, ToolCall>>,
+    after_tool_call: Option<AfterToolCall<'a>>,
+    stop_when: Option<StopCondition<'a>>,
+}
+
+/// How a turn of the agent ended.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum TurnOutcome {
+    /// The model replied without calling any tools, so it's the user's turn.
+    Reply(ChatResponse),
+    /// A tool or the stop condition ended the conversation.
+    Stopped,
+    /// The model was still calling tools after the maximum number of sends.
+    MaxIterations,
+}
+
+impl<'a> Agent<'a> {
+    /// Installs the tools into the chat. The chat should not have any of the tools already.
+    pub fn new(mut chat: Chat, tools: ToolRegistry) -> Result<Self, ToolError> {
+        tools.install(&mut chat)?;
+
+        Ok(Self {
+            chat,
+            tools,
+            max_iterations: 10,
+            on_chunk: None,
+            on_response: None,
+            before_tool_call: None,
+            after_tool_call: None,
+            stop_when: None,
+        })
+    }
+
+    /// How many times the chat can be sent in one turn, guarding against a model that never
+    /// stops calling tools. Defaults to 10.
+    pub fn max_iterations(mut self, max_iterations: u32) -> Self {
+        self.max_iterations = max_iterations.max(1);
+
+        self
+    }
+
+    /// Streams every reply, passing each partial message to `on_chunk` as it arrives.
+    pub fn on_chunk(mut self, on_chunk: impl FnMut(&Message) + 'a) -> Self {
+        self.on_chunk = Some(Box::new(on_chunk));
+
+        self
+    }
+
+    /// Called with every response from Ollama, including the ones that only call tools.
+    pub fn on_response(mut self, on_response: impl FnMut(&ChatResponse) + 'a) -> Self {
+        self.on_response = Some(Box::new(on_response));
+
+        self
+    }
+
+    pub fn before_tool_call(mut self, before_tool_call: impl FnMut(&ToolCall) + 'a) -> Self {
+        self.before_tool_call = Some(Box::new(before_tool_call));
+
+        self
+    }
+
+    pub fn after_tool_call(
+        mut self,
+        after_tool_call: impl FnMut(&ToolCall, &ToolResult) + 'a,
+    ) -> Self {
+        self.after_tool_call = Some(Box::new(after_tool_call));
+
+        self
+    }
+
+    /// Ends the turn as soon as the model sends a message matching the condition, before any of
+    /// its tool calls are run.
+    pub fn stop_when(mut self, stop_when: impl FnMut(&Message) -> bool + 'a) -> Self {
+        self.stop_when = Some(Box::new(stop_when));
+
+        self
+    }
+
+    /// Adds the user's message to the chat and runs a turn.
+    pub fn send(&mut self, message: Message) -> Result<TurnOutcome, OllamaError> {
+        self.chat.add_message(message);
+
+        self.run_turn()
+    }
+
+    /// Sends the chat and runs tool calls until the model replies with text, something stops the
+    /// conversation, or the iteration limit is reached.
+    pub fn run_turn(&mut self) -> Result<TurnOutcome, OllamaError> {
+        for _ in 0..self.max_iterations {
+            let response = self.send_chat()?;
+
+            if let Some(on_response) = &mut self.on_response {
+                on_response(&response);
+            }
+
+            if !self.chat.save_messages {
+                self.chat.add_message(response.message.clone());
+            }
+
+            if let Some(stop_when) = &mut self.stop_when {
+                if stop_when(&response.message) {
+                    return Ok(TurnOutcome::Stopped);
+                }
+            }
+
+            let Some(tool_calls) = response.message.tool_calls.as_ref() else {
+                return Ok(TurnOutcome::Reply(response));
+            };
+
+            if self.run_tool_calls(tool_calls) == ToolControl::Stop {
+                return Ok(TurnOutcome::Stopped);
+            }
+        }
+
+        Ok(TurnOutcome::MaxIterations)
+    }
+
+    fn send_chat(&mut self) -> Result<ChatResponse, OllamaError> {
+        match &mut self.on_chunk {
+            Some(on_chunk) => self.chat.send_stream_for_response(on_chunk),
+            None => self.chat.send_for_response(),
+        }
+    }
+
+    fn run_tool_calls(&mut self, tool_calls: &[ToolCall]) -> ToolControl {
+        for tool_call in tool_calls {
+            if let Some(before_tool_call) = &mut self.before_tool_call {
+                before_tool_call(tool_call);
+            }
+
+            let result = self.tools.dispatch(tool_call);
+
+            if let Some(after_tool_call) = &mut self.after_tool_call {
+                after_tool_call(tool_call, &result);
+            }
+
+            self.chat
+                .add_message(Message::new_tool_result(tool_call, result.content));
+
+            if result.control == ToolControl::Stop {
+                return ToolControl::Stop;
+            }
+        }
+
+        ToolControl::Continue
+    }
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    #[cfg(test)]
+    use crate::fake_ollama::{fake_ollama, in_order};
+    #[allow(unused_imports)]
+    use crate::{api::OllamaClient, chat::tool_registry::tool_fn, models::tool::OllamaTool};
+    #[allow(unused_imports)]
+    use std::cell::RefCell;
+
+    /// Look up the weather.
+    #[allow(dead_code)]
+    #[derive(OllamaTool)]
+    struct CheckWeather {
+        location: String,
+    }
+
+    /// Stop the conversation.
+    #[allow(dead_code)]
+    #[derive(OllamaTool)]
+    struct Quit;
+
+    #[allow(dead_code)]
+    fn agent<'a>(client: OllamaClient) -> Agent<'a> {
+        let mut tools = ToolRegistry::new();
+        tools
+            .register(tool_fn(|CheckWeather { location }| {
+                ToolResult::new(format!("It is sunny in {location}"))
+            }))
+            .unwrap();
+        tools
+            .register(tool_fn(|Quit| ToolResult::stop("bye")))
+            .unwrap();
+
+        let mut chat = Chat::new("llama3.1", None);
+        chat.set_client(client);
+
+        Agent::new(chat, tools).unwrap()
+    }
+
+    #[allow(dead_code)]
+    const WEATHER_CALL: &str = r#"{"model":"llama3.1","message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"check_weather","arguments":{"location":"Denver"}}}]},"done":true}"#;
+
+    #[test]
+    fn should_run_tools_until_the_model_replies() -> Result<(), OllamaError> {
+        let (client, _) = fake_ollama(in_order(vec![
+            WEATHER_CALL,
+            r#"{"model":"llama3.1","message":{"role":"assistant","content":"It's sunny!"},"done":true}"#,
+        ]));
+        let calls = RefCell::new(vec![]);
+        let mut agent = agent(client)
+            .before_tool_call(|tool_call| calls.borrow_mut().push(tool_call.function.name.clone()))
+            .after_tool_call(|_, result| calls.borrow_mut().push(result.content.clone()));
+
+        let outcome = agent.send(Message::new_user("What's the weather in Denver?"))?;
+
+        assert!(
+            matches!(outcome, TurnOutcome::Reply(response) if response.message.content == "It's sunny!")
+        );
+        assert_eq!(
+            agent
+                .chat
+                .messages
+                .iter()
+                .map(|message| message.content.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "What's the weather in Denver?",
+                "",
+                "It is sunny in Denver",
+                "It's sunny!"
+            ]
+        );
+        drop(agent);
+        assert_eq!(
+            calls.into_inner(),
+            vec![
+                "check_weather".to_owned(),
+                "It is sunny in Denver".to_owned()
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_stop_when_a_tool_asks_to() -> Result<(), OllamaError> {
+        let (client, _) = fake_ollama(in_order(vec![
+            r#"{"model":"llama3.1","message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"quit","arguments":{}}}]},"done":true}"#,
+        ]));
+        let mut agent = agent(client);
+
+        assert!(matches!(
+            agent.send(Message::new_user("Goodbye"))?,
+            TurnOutcome::Stopped
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_run_tool_calls_in_order_until_one_stops() -> Result<(), OllamaError> {
+        let (client, _) = fake_ollama(in_order(vec![
+            r#"{"model":"llama3.1","message":{"role":"assistant","content":"","tool_calls":[
+                {"id":"call_1","function":{"name":"check_weather","arguments":{"location":"Denver"}}},
+                {"id":"call_2","function":{"name":"quit","arguments":{}}},
+                {"function":{"name":"check_weather","arguments":{"location":"Boulder"}}}
+            ]},"done":true}"#,
+        ]));
+        let mut agent = agent(client);
+
+        let outcome = agent.send(Message::new_user("What's the weather? Then goodbye"))?;
+        let tool_results = &agent.chat.messages[2..];
+
+        assert!(matches!(outcome, TurnOutcome::Stopped));
+        assert_eq!(
+            tool_results
+                .iter()
+                .map(|message| message.content.as_str())
+                .collect::<Vec<_>>(),
+            vec!["It is sunny in Denver", "bye"]
+        );
+        assert_eq!(tool_results[1].tool_name.as_deref(), Some("quit"));
+        assert_eq!(agent.chat.tools.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_give_up_after_the_maximum_iterations() -> Result<(), OllamaError> {
+        let (client, _) = fake_ollama(in_order(vec![WEATHER_CALL, WEATHER_CALL, WEATHER_CALL]));
+        let mut agent = agent(client).max_iterations(2);
+
+        assert!(matches!(
+            agent.send(Message::new_user("What's the weather in Denver?"))?,
+            TurnOutcome::MaxIterations
+        ));
+        assert_eq!(agent.chat.usage.requests, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_stop_when_the_stop_condition_matches() -> Result<(), OllamaError> {
+        let (client, _) = fake_ollama(in_order(vec![WEATHER_CALL]));
+        let mut agent = agent(client).stop_when(|message| message.tool_calls.is_some());
+
+        assert!(matches!(
+            agent.send(Message::new_user("What's the weather in Denver?"))?,
+            TurnOutcome::Stopped
+        ));
+        assert_eq!(agent.chat.messages.len(), 2);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+use std::{collections::HashMap, marker::PhantomData};
+
+use serde::Serialize;
+
+use crate::models::{
+    chat_request::Chat,
+    tool::{OllamaTool, Tool, ToolError},
+    tool_call::{Arguments, ToolCall},
+};
+
+/// Something the model can call. `definition` is what the model is shown and `call` runs the tool
+/// with the arguments the model sent.
+pub trait ToolHandler {
+    fn definition(&self) -> Result<Tool, ToolError>;
+
+    fn call(&mut self, arguments: &Arguments) -> ToolResult;
+}
+
+/// What a tool call produced. The content is sent back to the model as a tool message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolResult {
+    pub content: String,
+    pub control: ToolControl,
+}
+
+impl ToolResult {
+    pub fn new(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            control: ToolControl::Continue,
+        }
+    }
+
+    /// A result holding `value` as JSON, so that several values come back to the model as one
+    /// result it can read reliably.
+    pub fn json(value: &impl Serialize) -> Self {
+        match serde_json::to_string(value) {
+            Ok(content) => Self::new(content),
+            Err(error) => Self::new(format!(
+                "Error, the result could not be written as JSON: {error}"
+            )),
+        }
+    }
+
+    /// A result after which the conversation should end, for example from a quit tool.
+    pub fn stop(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            control: ToolControl::Stop,
+        }
+    }
+
+    /// Tells the model its arguments didn't fit the tool, so that it can try again.
+    pub fn invalid_arguments(tool_name: &str, error: impl std::fmt::Display) -> Self {
+        Self::new(format!(
+            "Error, the arguments passed into the {tool_name} tool were not valid ({error}), please try again with the correct arguments"
+        ))
+    }
+
+    pub fn is_stop(&self) -> bool {
+        self.control == ToolControl::Stop
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ToolControl {
+    #[default]
+    Continue,
+    Stop,
+}
+
+/// A handler for an `OllamaTool` struct made from a closure that takes the parsed arguments, see
+/// `tool_fn`.
+pub struct FnTool<T, F> {
+    handler: F,
+    arguments: PhantomData<fn() -> T>,
+}
+
+/// Handles the tool `T` describes with a closure. Arguments that can't be parsed into `T` are
+/// answered with `ToolResult::invalid_arguments` without calling the closure.
+pub fn tool_fn<T, F>(handler: F) -> FnTool<T, F>
+where
+    T: OllamaTool,
+    F: FnMut(T) -> ToolResult,
+{
+    FnTool {
+        handler,
+        arguments: PhantomData,
+    }
+}
+
+impl<T, F> ToolHandler for FnTool<T, F>
+where
+    T: OllamaTool,
+    F: FnMut(T) -> ToolResult,
+{
+    fn definition(&self) -> Result<Tool, ToolError> {
+        T::tool()
+    }
+
+    fn call(&mut self, arguments: &Arguments) -> ToolResult {
+        match T::from_arguments(arguments) {
+            Ok(arguments) => (self.handler)(arguments),
+            Err(error) => ToolResult::invalid_arguments(T::name(), error),
+        }
+    }
+}
+
+/// The tools available to a chat, which runs the tool calls the model makes by name.
+#[derive(Default)]
+pub struct ToolRegistry {
+    tools: Vec<Tool>,
+    handlers: HashMap<String, Box<dyn ToolHandler>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, handler: impl ToolHandler + 'static) -> Result<(), ToolError> {
+        let tool = handler.definition()?;
+        let name = tool.function.name.clone();
+
+        if self.handlers.contains_key(&name) {
+            return Err(ToolError::DuplicateTool(name));
+        }
+
+        self.tools.push(tool);
+        self.handlers.insert(name, Box::new(handler));
+
+        Ok(())
+    }
+
+    /// The definitions of every registered tool in the order they were registered.
+    pub fn tools(&self) -> &[Tool] {
+        &self.tools
+    }
+
+    /// Makes every registered tool available to the chat.
+    pub fn install(&self, chat: &mut Chat) -> Result<(), ToolError> {
+        self.tools
+            .iter()
+            .try_for_each(|tool| chat.add_tool(tool.clone()))
+    }
+
+    pub fn dispatch(&mut self, tool_call: &ToolCall) -> ToolResult {
+        let name = &tool_call.function.name;
+
+        match self.handlers.get_mut(name) {
+            Some(handler) => handler.call(&tool_call.function.arguments),
+            None => ToolResult::new(format!(
+                "There is no tool named '{name}', please try again with one of these tools: {}",
+                self.tool_names().join(", ")
+            )),
+        }
+    }
+
+    fn tool_names(&self) -> Vec<&str> {
+        self.tools
+            .iter()
+            .map(|tool| tool.function.name.as_str())
+            .collect()
+    }
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use crate::models::tool::OllamaTool;
+
+    /// Add two numbers.
+    #[allow(dead_code)]
+    #[derive(OllamaTool)]
+    struct Add {
+        a: i64,
+        b: i64,
+    }
+
+    /// Stop the conversation.
+    #[allow(dead_code)]
+    #[derive(OllamaTool)]
+    struct Quit;
+
+    #[allow(dead_code)]
+    fn tool_call(json: &str) -> ToolCall {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[allow(dead_code)]
+    fn registry() -> ToolRegistry {
+        let mut registry = ToolRegistry::new();
+
+        registry
+            .register(tool_fn(|Add { a, b }| ToolResult::new((a + b).to_string())))
+            .unwrap();
+        registry
+            .register(tool_fn(|Quit| ToolResult::stop("bye")))
+            .unwrap();
+
+        registry
+    }
+
+    #[test]
+    fn should_dispatch_tool_calls_by_name() {
+        let mut registry = registry();
+
+        let result = registry.dispatch(&tool_call(
+            r#"{"function":{"name":"add","arguments":{"a":2,"b":"3"}}}"#,
+        ));
+
+        assert_eq!(result, ToolResult::new("5"));
+    }
+
+    #[test]
+    fn should_tell_the_model_about_unknown_tools_and_bad_arguments() {
+        let mut registry = registry();
+
+        let unknown = registry.dispatch(&tool_call(
+            r#"{"function":{"name":"subtract","arguments":{}}}"#,
+        ));
+        let invalid = registry.dispatch(&tool_call(
+            r#"{"function":{"name":"add","arguments":{"a":2}}}"#,
+        ));
+
+        assert!(unknown.content.contains("add, quit"));
+        assert!(invalid.content.contains("missing field `b`"));
+        assert!(!invalid.is_stop());
+    }
+
+    #[test]
+    fn should_reject_registering_a_tool_twice() {
+        let mut registry = registry();
+
+        let result = registry.register(tool_fn(|Quit| ToolResult::stop("again")));
+
+        assert_eq!(result, Err(ToolError::DuplicateTool("quit".to_owned())));
+    }
+
+    #[test]
+    fn should_return_values_as_a_single_json_result() {
+        let result = ToolResult::json(&vec![serde_json::json!({ "id": 1, "name": "Pet Xilbe" })]);
+
+        assert_eq!(result.content, r#"[{"id":1,"name":"Pet Xilbe"}]"#);
+    }
+}
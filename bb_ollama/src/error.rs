@@ -0,0 +1,216 @@
+use std::fmt::Display;
+
+use serde::Deserialize;
+
+use crate::schema::SchemaError;
+
+/// Everything that can go wrong while talking to Ollama, split up so that callers can decide
+/// whether to retry, pull a missing model, or give up.
+#[derive(Debug)]
+pub enum OllamaError {
+    /// Ollama could not be reached at all, usually because it isn't running.
+    Connection(reqwest::Error),
+    /// Ollama was reached but didn't answer before the request timed out.
+    Timeout(reqwest::Error),
+    /// The requested model hasn't been pulled onto the Ollama server.
+    ModelNotFound(String),
+    /// Ollama answered with an error status and a body that wasn't one of its own errors.
+    Http {
+        status: u16,
+        body: String,
+    },
+    /// Ollama reported an error of its own with a `{"error": ...}` body.
+    Ollama {
+        status: Option<u16>,
+        message: String,
+    },
+    /// The response from Ollama could not be understood.
+    MalformedJson(serde_json::Error),
+    Io(std::io::Error),
+    InvalidUrl(String),
+    Request(reqwest::Error),
+    /// The type asked for with `Chat::send_structured` could not be described as a schema.
+    Schema(SchemaError),
+    /// The model replied, but not in the structure that was asked for.
+    StructuredOutput {
+        content: String,
+        source: serde_json::Error,
+    },
+    /// Every attempt allowed by the retry policy failed, `last_error` is the final failure.
+    RetriesExhausted {
+        attempts: u32,
+        last_error: Box<OllamaError>,
+    },
+}
+
+impl OllamaError {
+    /// Whether sending the same request again might succeed. Problems reaching Ollama and
+    /// server side failures are worth another try, while a bad request or missing model are not.
+    /// Neither is a timeout, since a request that used up the whole timeout would most likely do
+    /// it again and keep the caller waiting several times as long.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Connection(_) | Self::Io(_) => true,
+            Self::Http { status, .. }
+            | Self::Ollama {
+                status: Some(status),
+                ..
+            } => *status == 429 || *status >= 500,
+            Self::Timeout(_)
+            | Self::ModelNotFound(_)
+            | Self::Ollama { status: None, .. }
+            | Self::MalformedJson(_)
+            | Self::InvalidUrl(_)
+            | Self::Request(_)
+            | Self::Schema(_)
+            | Self::StructuredOutput { .. }
+            | Self::RetriesExhausted { .. } => false,
+        }
+    }
+
+    pub fn is_model_not_found(&self) -> bool {
+        matches!(self, Self::ModelNotFound(_))
+    }
+
+    /// Converts an error status from Ollama into the matching error, using the error message
+    /// from the body when Ollama sent one.
+    pub(crate) fn from_status(status: u16, body: String) -> Self {
+        let Ok(ErrorBody { error }) = serde_json::from_str::<ErrorBody>(&body) else {
+            return Self::Http { status, body };
+        };
+
+        if status == 404 && error.contains("not found") {
+            Self::ModelNotFound(error)
+        } else {
+            Self::Ollama {
+                status: Some(status),
+                message: error,
+            }
+        }
+    }
+}
+
+impl Display for OllamaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Connection(error) => write!(f, "could not connect to Ollama: {error}"),
+            Self::Timeout(error) => write!(f, "timed out waiting for Ollama: {error}"),
+            Self::ModelNotFound(message) => write!(f, "model not found: {message}"),
+            Self::Http { status, body } => {
+                write!(f, "Ollama responded with status {status}: {body}")
+            }
+            Self::Ollama {
+                status: Some(status),
+                message,
+            } => write!(f, "Ollama responded with status {status}: {message}"),
+            Self::Ollama {
+                status: None,
+                message,
+            } => write!(f, "Ollama reported an error: {message}"),
+            Self::MalformedJson(error) => {
+                write!(f, "could not understand the response from Ollama: {error}")
+            }
+            Self::Io(error) => write!(f, "error reading the response from Ollama: {error}"),
+            Self::InvalidUrl(url) => write!(f, "'{url}' is not a valid Ollama url"),
+            Self::Request(error) => write!(f, "error sending request to Ollama: {error}"),
+            Self::Schema(error) => write!(f, "{error}"),
+            Self::StructuredOutput { content, source } => write!(
+                f,
+                "the reply from the model did not match the requested structure: {source}, the reply was: {content}"
+            ),
+            Self::RetriesExhausted {
+                attempts,
+                last_error,
+            } => write!(f, "gave up after {attempts} attempts: {last_error}"),
+        }
+    }
+}
+
+impl std::error::Error for OllamaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Connection(error) | Self::Timeout(error) | Self::Request(error) => Some(error),
+            Self::MalformedJson(error) | Self::StructuredOutput { source: error, .. } => {
+                Some(error)
+            }
+            Self::Schema(error) => Some(error),
+            Self::Io(error) => Some(error),
+            Self::RetriesExhausted { last_error, .. } => Some(last_error.as_ref()),
+            Self::ModelNotFound(_)
+            | Self::Http { .. }
+            | Self::Ollama { .. }
+            | Self::InvalidUrl(_) => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for OllamaError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Self::Timeout(error)
+        } else if error.is_connect() {
+            Self::Connection(error)
+        } else {
+            Self::Request(error)
+        }
+    }
+}
+
+impl From<serde_json::Error> for OllamaError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::MalformedJson(error)
+    }
+}
+
+impl From<SchemaError> for OllamaError {
+    fn from(error: SchemaError) -> Self {
+        Self::Schema(error)
+    }
+}
+
+impl From<std::io::Error> for OllamaError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// The body Ollama sends back when something went wrong on its side.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ErrorBody {
+    pub error: String,
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn should_recognise_a_missing_model() {
+        let error = OllamaError::from_status(
+            404,
+            r#"{"error":"model \"llama9\" not found, try pulling it first"}"#.to_owned(),
+        );
+
+        assert!(error.is_model_not_found());
+        assert!(!error.is_retryable());
+    }
+
+    #[test]
+    fn should_keep_the_body_of_unknown_errors() {
+        let error = OllamaError::from_status(502, "Bad Gateway".to_owned());
+
+        assert!(matches!(&error, OllamaError::Http { status: 502, body } if body == "Bad Gateway"));
+        assert!(error.is_retryable());
+    }
+
+    #[test]
+    fn should_use_the_message_from_ollama_errors() {
+        let error = OllamaError::from_status(400, r#"{"error":"invalid options"}"#.to_owned());
+
+        assert_eq!(
+            error.to_string(),
+            "Ollama responded with status 400: invalid options"
+        );
+        assert!(!error.is_retryable());
+    }
+}
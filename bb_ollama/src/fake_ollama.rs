@@ -0,0 +1,85 @@
+//! A stand-in for the Ollama server so that tests can run the request loop without a model.
+
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use crate::{api::OllamaClient, retry::RetryPolicy};
+
+/// The paths of the requests the fake server has received, in order.
+pub type Requests = Arc<Mutex<Vec<String>>>;
+
+/// Answers every request with the body `reply` returns for its path, and records the paths.
+pub fn fake_ollama(reply: impl Fn(&str) -> String + Send + 'static) -> (OllamaClient, Requests) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Requests::default();
+
+    thread::spawn({
+        let requests = requests.clone();
+
+        move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                let mut content_length = 0;
+
+                reader.read_line(&mut request_line).unwrap();
+
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+
+                    if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let path = request_line
+                    .split_whitespace()
+                    .nth(1)
+                    .unwrap_or_default()
+                    .to_owned();
+                let body = reply(&path);
+                requests.lock().unwrap().push(path);
+
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        }
+    });
+
+    let client = OllamaClient::new(url.parse().unwrap()).retry_policy(RetryPolicy::none());
+
+    (client, requests)
+}
+
+/// Replies with each of the bodies in turn, whatever the request was.
+pub fn in_order(replies: Vec<&'static str>) -> impl Fn(&str) -> String + Send + 'static {
+    let replies = Mutex::new(VecDeque::from(replies));
+
+    move |_| {
+        replies
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_default()
+            .to_owned()
+    }
+}
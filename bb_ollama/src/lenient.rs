@@ -0,0 +1,332 @@
+//! A forgiving way to read the JSON that models produce. Models regularly send `"3"` where a
+//! number was asked for or `"true"` for a boolean, so when deserializing through `Lenient` strings
+//! are converted into the numbers and booleans the target type expects, numbers and booleans are
+//! accepted where a string is expected, and stringified arrays and objects are parsed. An empty
+//! string where an `Option` is expected is read as `None`, since that's how models leave it out.
+
+use serde::{
+    de::{value::StrDeserializer, DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    forward_to_deserialize_any, Deserializer,
+};
+use serde_json::{Error, Value};
+
+pub struct Lenient<'a>(pub &'a Value);
+
+impl<'a> Lenient<'a> {
+    fn trimmed_string(&self) -> Option<&'a str> {
+        match self.0 {
+            Value::String(value) => Some(value.trim()),
+            _ => None,
+        }
+    }
+
+    fn visit_integer<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if let Some(value) = self.trimmed_string() {
+            if let Ok(value) = value.parse::<i64>() {
+                return visitor.visit_i64(value);
+            }
+
+            if let Ok(value) = value.parse::<u64>() {
+                return visitor.visit_u64(value);
+            }
+        }
+
+        if let Some(value) = self.0.as_f64().filter(|value| value.fract() == 0.0) {
+            if self.0.is_f64() {
+                return visitor.visit_i64(value as i64);
+            }
+        }
+
+        self.0.clone().deserialize_any(visitor)
+    }
+
+    fn visit_float<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if let Some(value) = self.trimmed_string().and_then(|value| value.parse().ok()) {
+            return visitor.visit_f64(value);
+        }
+
+        self.0.clone().deserialize_any(visitor)
+    }
+
+    /// Arrays and objects sometimes arrive as a string holding their JSON.
+    fn visit_nested<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Array(values) => visitor.visit_seq(LenientSeq(values.iter())),
+            Value::Object(values) => visitor.visit_map(LenientMap {
+                entries: values.iter(),
+                value: None,
+            }),
+            Value::String(value) => match serde_json::from_str::<Value>(value) {
+                Ok(parsed @ (Value::Array(_) | Value::Object(_))) => {
+                    Lenient(&parsed).visit_nested(visitor)
+                }
+                _ => self.0.clone().deserialize_any(visitor),
+            },
+            _ => self.0.clone().deserialize_any(visitor),
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for Lenient<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Array(_) | Value::Object(_) => self.visit_nested(visitor),
+            _ => self.0.clone().deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.trimmed_string().map(str::to_lowercase).as_deref() {
+            Some("true") => visitor.visit_bool(true),
+            Some("false") => visitor.visit_bool(false),
+            _ => self.0.clone().deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_integer(visitor)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_integer(visitor)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_integer(visitor)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_integer(visitor)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_integer(visitor)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_integer(visitor)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_integer(visitor)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_integer(visitor)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_float(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_float(visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Number(value) => visitor.visit_string(value.to_string()),
+            Value::Bool(value) => visitor.visit_string(value.to_string()),
+            _ => self.0.clone().deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            _ if self.trimmed_string() == Some("") => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_nested(visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.visit_nested(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.visit_nested(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_nested(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.visit_nested(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0.clone().deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        char bytes byte_buf unit unit_struct identifier ignored_any
+    }
+}
+
+struct LenientSeq<'a>(std::slice::Iter<'a, Value>);
+
+impl<'de> SeqAccess<'de> for LenientSeq<'_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        self.0
+            .next()
+            .map(|value| seed.deserialize(Lenient(value)))
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+struct LenientMap<'a> {
+    entries: serde_json::map::Iter<'a>,
+    value: Option<&'a Value>,
+}
+
+impl<'de> MapAccess<'de> for LenientMap<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let Some((key, value)) = self.entries.next() else {
+            return Ok(None);
+        };
+
+        self.value = Some(value);
+
+        seed.deserialize(StrDeserializer::new(key)).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let value = self.value.take().unwrap_or(&Value::Null);
+
+        seed.deserialize(Lenient(value))
+    }
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use serde::Deserialize;
+    #[allow(unused_imports)]
+    use serde_json::json;
+
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct UpdateTask {
+        id: i32,
+        name: Option<String>,
+        completed: Option<bool>,
+        estimate: Option<f32>,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn should_coerce_stringified_values() -> Result<(), Error> {
+        let arguments = json!({
+            "id": "3",
+            "name": 42,
+            "completed": "True",
+            "estimate": "1.5",
+            "tags": "[\"home\", \"errands\"]"
+        });
+
+        let update = UpdateTask::deserialize(Lenient(&arguments))?;
+
+        assert_eq!(
+            update,
+            UpdateTask {
+                id: 3,
+                name: Some("42".to_owned()),
+                completed: Some(true),
+                estimate: Some(1.5),
+                tags: vec!["home".to_owned(), "errands".to_owned()],
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_accept_values_that_are_already_the_right_type() -> Result<(), Error> {
+        let arguments = json!({ "id": 3.0, "name": null, "completed": false, "tags": [] });
+
+        let update = UpdateTask::deserialize(Lenient(&arguments))?;
+
+        assert_eq!(update.id, 3);
+        assert_eq!(update.name, None);
+        assert_eq!(update.completed, Some(false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_read_blank_optional_values_as_missing() -> Result<(), Error> {
+        let arguments =
+            json!({ "id": 3, "name": "", "completed": " ", "estimate": "", "tags": [] });
+
+        let update = UpdateTask::deserialize(Lenient(&arguments))?;
+
+        assert_eq!(update.name, None);
+        assert_eq!(update.completed, None);
+        assert_eq!(update.estimate, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_still_reject_values_that_cannot_be_coerced() {
+        let arguments = json!({ "id": "three", "tags": [] });
+
+        assert!(UpdateTask::deserialize(Lenient(&arguments)).is_err());
+    }
+}
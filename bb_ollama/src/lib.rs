@@ -1,3 +1,15 @@
+// lets `#[derive(OllamaTool)]` refer to `::bb_ollama` from inside this crate too
+extern crate self as bb_ollama;
+
 pub mod api;
 pub mod chat;
+pub mod error;
+#[cfg(test)]
+mod fake_ollama;
+pub mod lenient;
 pub mod models;
+pub mod retry;
+pub mod schema;
+
+#[doc(hidden)]
+pub use serde_json;
@@ -1,9 +1,19 @@
-use eyre::{Context, Result};
-use serde::{Deserialize, Serialize};
-
-use crate::api;
-
-use super::{message::Message, options::ChatRequestOptions, tool::Tool};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    api::{ChatResponse, OllamaClient},
+    error::OllamaError,
+};
+
+use super::{
+    format::Format,
+    generate_request::Generate,
+    history::{summary_prompt, HistoryPolicy, SUMMARY_PREFIX},
+    message::{Message, Role},
+    options::{ChatRequestOptions, ModelOptions},
+    tool::{Tool, ToolError},
+    usage::ChatUsage,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Chat {
@@ -12,15 +22,38 @@ pub struct Chat {
     pub stream: Option<bool>,
     pub raw: Option<bool>,
     pub tools: Vec<Tool>,
-    pub options: Option<ChatRequestOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<ModelOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<Format>,
+    #[serde(skip)]
+    pub save_messages: bool,
+    #[serde(skip)]
+    pub client: OllamaClient,
+    #[serde(skip)]
+    pub usage: ChatUsage,
+    #[serde(skip)]
+    pub history_policy: HistoryPolicy,
+    /// Why the last summary of dropped turns could not be written. The turns are dropped without
+    /// a summary rather than failing the send, take this to find out when that happened.
+    #[serde(skip)]
+    pub summary_error: Option<OllamaError>,
 }
 
 impl Chat {
     pub fn new(model: impl Into<String>, options: Option<ChatRequestOptions>) -> Self {
-        let messages = vec![];
+        let options = options.unwrap_or_default();
+        let messages = options
+            .system
+            .map(Message::new_system)
+            .into_iter()
+            .collect();
         let stream = Some(false);
         let raw = Some(false);
         let tools = vec![];
+        let model_options = Some(options.model_options).filter(|options| !options.is_empty());
 
         Self {
             model: model.into(),
@@ -28,40 +61,167 @@ impl Chat {
             stream,
             raw,
             tools,
-            options,
+            options: model_options,
+            keep_alive: options.keep_alive,
+            format: None,
+            save_messages: options.save_messages,
+            client: OllamaClient::default(),
+            usage: ChatUsage::default(),
+            history_policy: HistoryPolicy::default(),
+            summary_error: None,
         }
     }
 
+    pub fn set_client(&mut self, client: OllamaClient) {
+        self.client = client;
+    }
+
+    /// Constrains every reply to the given format until it is cleared with `None`.
+    pub fn set_format(&mut self, format: Option<Format>) {
+        self.format = format;
+    }
+
+    /// Limits how much history is sent with each request, by default all of it is.
+    pub fn set_history_policy(&mut self, history_policy: HistoryPolicy) {
+        self.history_policy = history_policy;
+    }
+
     pub fn add_message(&mut self, message: Message) {
         self.messages.push(message)
     }
 
-    pub fn add_tool(&mut self, tool: Tool) {
+    /// Adds a tool the model can call, as long as the chat doesn't have one with the same name.
+    pub fn add_tool(&mut self, tool: Tool) -> Result<(), ToolError> {
+        if self
+            .tools
+            .iter()
+            .any(|existing| existing.function.name == tool.function.name)
+        {
+            return Err(ToolError::DuplicateTool(tool.function.name));
+        }
+
         self.tools.push(tool);
+
+        Ok(())
     }
 
-    pub fn send(&mut self) -> Result<Message> {
-        let message = api::send_to_ollama(&self).context("Sending chat to Ollama")?;
+    pub fn send(&mut self) -> Result<Message, OllamaError> {
+        Ok(self.send_for_response()?.message)
+    }
 
-        if self
-            .options
-            .as_ref()
-            .is_some_and(|options| options.save_messages)
-        {
-            self.add_message(message.clone());
+    /// Like `send`, but returns the whole response from Ollama including the token counts and
+    /// timings for this turn.
+    pub fn send_for_response(&mut self) -> Result<ChatResponse, OllamaError> {
+        self.trim_history();
+
+        let response = self.client.chat(self)?;
+
+        self.record_response(&response);
+
+        Ok(response)
+    }
+
+    /// Sends the chat asking the model to reply with JSON matching the schema of `T`, and
+    /// deserializes the reply into it. Any format set on the chat is only replaced for this send.
+    pub fn send_structured<T: DeserializeOwned>(&mut self) -> Result<T, OllamaError> {
+        let format = Format::schema_for::<T>()?;
+        let previous_format = self.format.replace(format);
+        let result = self.send();
+        self.format = previous_format;
+
+        let message = result?;
+
+        serde_json::from_str(&message.content).map_err(|source| OllamaError::StructuredOutput {
+            content: message.content,
+            source,
+        })
+    }
+
+    /// Sends the chat asking Ollama to stream its reply. Every partial message is passed to
+    /// `on_chunk` as soon as it arrives, and the assembled message is returned once the reply is
+    /// complete.
+    pub fn send_stream(&mut self, on_chunk: impl FnMut(&Message)) -> Result<Message, OllamaError> {
+        Ok(self.send_stream_for_response(on_chunk)?.message)
+    }
+
+    pub fn send_stream_for_response(
+        &mut self,
+        on_chunk: impl FnMut(&Message),
+    ) -> Result<ChatResponse, OllamaError> {
+        self.trim_history();
+
+        self.stream = Some(true);
+        let result = self.client.chat_stream(self, on_chunk);
+        self.stream = Some(false);
+
+        let response = result?;
+
+        self.record_response(&response);
+
+        Ok(response)
+    }
+
+    /// Drops the messages the history policy doesn't have room for, replacing them with a
+    /// summary when the policy has a model to write one. This runs before every send.
+    pub fn trim_history(&mut self) {
+        let (mut kept, dropped) = self.history_policy.split(self.messages.clone());
+
+        if dropped.is_empty() {
+            return;
         }
 
-        Ok(message)
+        if let Some(model) = &self.history_policy.summary_model {
+            let mut request = Generate::new(model, summary_prompt(&dropped));
+            // the dropped turns can be as long as the chat's history, so give them the same room
+            if let Some(num_ctx) = self.options.as_ref().and_then(|options| options.num_ctx) {
+                request = request.options(ModelOptions::new().num_ctx(num_ctx));
+            }
+            request.set_client(self.client.clone());
+
+            // the summary is only nice to have, so failing to write one shouldn't fail the send
+            match request.send() {
+                Ok(summary) => {
+                    let pinned = kept
+                        .iter()
+                        .take_while(|message| message.role == Role::System)
+                        .count();
+
+                    kept.insert(
+                        pinned,
+                        Message::new_system(format!(
+                            "{SUMMARY_PREFIX} {}",
+                            summary.response.trim()
+                        )),
+                    );
+                }
+                Err(error) => self.summary_error = Some(error),
+            }
+        }
+
+        self.messages = kept;
+    }
+
+    fn record_response(&mut self, response: &ChatResponse) {
+        self.usage.record(response);
+        self.save_response(&response.message);
+    }
+
+    fn save_response(&mut self, message: &Message) {
+        if self.save_messages {
+            self.add_message(message.clone());
+        }
     }
 }
 
 mod tests {
     #[allow(unused_imports)]
     use super::*;
+    #[cfg(test)]
+    use crate::fake_ollama::fake_ollama;
     #[allow(unused_imports)]
     use crate::models::tool::Property;
     #[allow(unused_imports)]
-    use eyre::OptionExt;
+    use eyre::{OptionExt, Result};
 
     #[test]
     fn can_send_and_receive_chat() -> Result<()> {
@@ -91,9 +251,9 @@ mod tests {
             .function_description("Get the weather in farhenheight degrees for any given location")
             .add_function_property("location", Property::new_string("location for where you want to check the weather. Use the format 'city, state' if the location is in the United States. Otherwise use 'city, country'"))
             .add_required_property("location")
-            .build();
+            .build()?;
 
-        chat.add_tool(weather_tool);
+        chat.add_tool(weather_tool)?;
         chat.add_message(message);
         let received_message = chat.send()?;
 
@@ -129,9 +289,9 @@ mod tests {
             .function_description("Get the weather in farhenheight degrees for any given location")
             .add_function_property("location", Property::new_string("location for where you want to check the weather. Use the format 'city, state' if the location is in the United States. Otherwise use 'city, country'"))
             .add_required_property("location")
-            .build();
+            .build()?;
 
-        chat.add_tool(weather_tool);
+        chat.add_tool(weather_tool)?;
         chat.add_message(message);
 
         let received_message = chat.send()?;
@@ -162,6 +322,151 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn should_keep_local_settings_out_of_the_request() -> Result<()> {
+        let chat = Chat::new(
+            "llama3.2:1b-instruct-fp16",
+            Some(
+                ChatRequestOptions::new()
+                    .system("You manage a todo list")
+                    .save_messages()
+                    .seed(123),
+            ),
+        );
+
+        let request = serde_json::to_value(&chat)?;
+
+        assert_eq!(request["options"], serde_json::json!({ "seed": 123 }));
+        assert_eq!(request["messages"][0]["role"], "system");
+        assert_eq!(request["messages"][0]["content"], "You manage a todo list");
+        assert!(request.get("save_messages").is_none());
+        assert!(request.get("keep_alive").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_reject_a_second_tool_with_the_same_name() -> Result<()> {
+        let mut chat = Chat::new("llama3.2:1b-instruct-fp16", None);
+        let weather_tool = || {
+            Tool::new()
+                .function_name("check_weather")
+                .function_description("Check the weather")
+                .build()
+        };
+
+        chat.add_tool(weather_tool()?)?;
+
+        assert_eq!(
+            chat.add_tool(weather_tool()?),
+            Err(ToolError::DuplicateTool("check_weather".to_owned()))
+        );
+        assert_eq!(chat.tools.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_trim_the_history_before_sending() -> Result<()> {
+        let mut chat = Chat::new(
+            "llama3.2:1b-instruct-fp16",
+            Some(ChatRequestOptions::new().system("You manage a todo list")),
+        );
+
+        chat.set_history_policy(HistoryPolicy::new().max_messages(2));
+        chat.add_message(Message::new_user("one"));
+        chat.add_message(Message::new_user("two"));
+        chat.trim_history();
+
+        assert_eq!(chat.messages.len(), 2);
+        assert_eq!(chat.messages[0].content, "You manage a todo list");
+        assert_eq!(chat.messages[1].content, "two");
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_not_summarise_on_every_send_once_the_history_is_full() -> Result<()> {
+        let (client, requests) = fake_ollama(|path| {
+            if path == "/api/generate" {
+                r#"{"model":"llama3.2","response":"The user listed their chores.","done":true}"#
+            } else {
+                r#"{"model":"llama3.2","message":{"role":"assistant","content":"Noted!"},"done":true}"#
+            }
+            .to_owned()
+        });
+        let mut chat = Chat::new("llama3.2", Some(ChatRequestOptions::new().save_messages()));
+        let sends = 12;
+
+        chat.set_client(client);
+        chat.set_history_policy(
+            HistoryPolicy::new()
+                .max_tokens(400)
+                .summarize_with("llama3.2"),
+        );
+
+        for chore in 0..sends {
+            chat.add_message(Message::new_user(format!(
+                "Please remember chore number {chore}, it needs doing before the weekend"
+            )));
+            chat.send()?;
+        }
+
+        let summaries = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|path| *path == "/api/generate")
+            .count();
+
+        assert!(summaries >= 1);
+        assert!(summaries <= sends / 4, "summarised {summaries} times");
+        assert!(chat.messages[0].content.starts_with(SUMMARY_PREFIX));
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_drop_old_turns_without_a_summary_when_summarising_fails() -> Result<()> {
+        let (client, _) = fake_ollama(|path| {
+            if path == "/api/generate" {
+                "not a summary"
+            } else {
+                r#"{"model":"llama3.2","message":{"role":"assistant","content":"Noted!"},"done":true}"#
+            }
+            .to_owned()
+        });
+        let mut chat = Chat::new("llama3.2", None);
+
+        chat.set_client(client);
+        chat.set_history_policy(
+            HistoryPolicy::new()
+                .max_messages(3)
+                .summarize_with("llama3.2"),
+        );
+        chat.add_message(Message::new_user("one"));
+        chat.add_message(Message::new_user("two"));
+        chat.add_message(Message::new_user("three"));
+
+        let reply = chat.send()?;
+
+        assert_eq!(reply.content, "Noted!");
+        assert!(matches!(
+            chat.summary_error.take(),
+            Some(OllamaError::MalformedJson(_))
+        ));
+        assert!(chat
+            .messages
+            .iter()
+            .all(|message| !message.content.starts_with(SUMMARY_PREFIX)));
+        assert_eq!(
+            chat.messages.last().map(|message| message.content.as_str()),
+            Some("three")
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn should_not_save_chats_into_history() -> Result<()> {
         let mut chat = Chat::new(
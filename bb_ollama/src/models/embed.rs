@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+
+/// A request to `/api/embed`. Every string in `input` gets its own embedding, in the same order.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EmbedRequest {
+    pub model: String,
+    pub input: Vec<String>,
+    /// Whether Ollama should cut inputs down to the context length instead of failing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncate: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
+}
+
+impl EmbedRequest {
+    pub fn new(
+        model: impl Into<String>,
+        input: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        Self {
+            model: model.into(),
+            input: input.into_iter().map(Into::into).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn truncate(mut self, truncate: bool) -> Self {
+        self.truncate = Some(truncate);
+
+        self
+    }
+
+    pub fn keep_alive(mut self, keep_alive: impl Into<String>) -> Self {
+        self.keep_alive = Some(keep_alive.into());
+
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EmbedResponse {
+    pub model: String,
+    pub embeddings: Vec<Vec<f32>>,
+    pub total_duration: Option<u64>,
+    pub load_duration: Option<u64>,
+    pub prompt_eval_count: Option<u64>,
+}
+
+/// How closely two embeddings point in the same direction, from `-1.0` to `1.0`. Useful for
+/// finding the stored text closest in meaning to a query.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot = a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+    let magnitude_a = a.iter().map(|a| a * a).sum::<f32>().sqrt();
+    let magnitude_b = b.iter().map(|b| b * b).sum::<f32>().sqrt();
+
+    if magnitude_a == 0.0 || magnitude_b == 0.0 {
+        return 0.0;
+    }
+
+    dot / (magnitude_a * magnitude_b)
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn should_serialize_only_the_set_fields() -> Result<(), serde_json::Error> {
+        let request = EmbedRequest::new("all-minilm", ["walk the dog", "take the dog to the vet"]);
+
+        assert_eq!(
+            serde_json::to_string(&request)?,
+            r#"{"model":"all-minilm","input":["walk the dog","take the dog to the vet"]}"#
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_parse_embeddings() -> Result<(), serde_json::Error> {
+        let body = r#"{"model":"all-minilm","embeddings":[[0.1,0.2],[0.3,0.4]],"total_duration":14143917,"load_duration":1019500,"prompt_eval_count":8}"#;
+
+        let response = serde_json::from_str::<EmbedResponse>(body)?;
+
+        assert_eq!(response.embeddings, vec![vec![0.1, 0.2], vec![0.3, 0.4]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_compare_embeddings_by_direction() {
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]), 1.0);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[-1.0, 0.0]), -1.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+}
@@ -0,0 +1,62 @@
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::schema::{schema_for, SchemaError};
+
+/// Constrains what the model is allowed to reply with, either any valid JSON or JSON matching a
+/// schema.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Format {
+    Json,
+    Schema(Value),
+}
+
+impl Format {
+    /// The schema for replies that deserialize into `T`, see `crate::schema` for which types can
+    /// be described.
+    pub fn schema_for<T: DeserializeOwned>() -> Result<Self, SchemaError> {
+        Ok(Self::Schema(schema_for::<T>()?))
+    }
+}
+
+impl Serialize for Format {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Json => serializer.serialize_str("json"),
+            Self::Schema(schema) => schema.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Format {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::String(format) if format == "json" => Ok(Self::Json),
+            schema => Ok(Self::Schema(schema)),
+        }
+    }
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use serde_json::json;
+
+    #[test]
+    fn should_serialize_the_way_ollama_expects() -> Result<(), serde_json::Error> {
+        let schema = json!({ "type": "array", "items": { "type": "string" } });
+
+        assert_eq!(serde_json::to_value(Format::Json)?, json!("json"));
+        assert_eq!(
+            serde_json::to_value(Format::Schema(schema.clone()))?,
+            schema
+        );
+        assert_eq!(
+            serde_json::from_value::<Format>(schema.clone())?,
+            Format::Schema(schema)
+        );
+
+        Ok(())
+    }
+}
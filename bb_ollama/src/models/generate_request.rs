@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{api::OllamaClient, error::OllamaError};
+
+use super::{format::Format, image::encode_image, options::ModelOptions};
+
+/// A one shot completion through `/api/generate`, for jobs that don't need a conversation or
+/// tools such as summarising a task list.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Generate {
+    pub model: String,
+    pub prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// Sends the prompt to the model exactly as written, without applying the prompt template.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<bool>,
+    /// Base64 encoded images for multimodal models.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<Format>,
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<ModelOptions>,
+    #[serde(skip)]
+    pub client: OllamaClient,
+}
+
+impl Generate {
+    pub fn new(model: impl Into<String>, prompt: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            prompt: prompt.into(),
+            system: None,
+            template: None,
+            raw: None,
+            images: None,
+            keep_alive: None,
+            format: None,
+            stream: Some(false),
+            options: None,
+            client: OllamaClient::default(),
+        }
+    }
+
+    pub fn system(mut self, system: impl Into<String>) -> Self {
+        self.system = Some(system.into());
+
+        self
+    }
+
+    pub fn template(mut self, template: impl Into<String>) -> Self {
+        self.template = Some(template.into());
+
+        self
+    }
+
+    pub fn raw(mut self) -> Self {
+        self.raw = Some(true);
+
+        self
+    }
+
+    pub fn add_image(mut self, base64_image: impl Into<String>) -> Self {
+        self.images
+            .get_or_insert_with(Vec::new)
+            .push(base64_image.into());
+
+        self
+    }
+
+    pub fn add_image_bytes(self, bytes: impl AsRef<[u8]>) -> Self {
+        self.add_image(encode_image(bytes))
+    }
+
+    /// How long Ollama should keep the model loaded afterwards, for example `"5m"` or `"0"`.
+    pub fn keep_alive(mut self, keep_alive: impl Into<String>) -> Self {
+        self.keep_alive = Some(keep_alive.into());
+
+        self
+    }
+
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = Some(format);
+
+        self
+    }
+
+    pub fn options(mut self, options: ModelOptions) -> Self {
+        self.options = Some(options);
+
+        self
+    }
+
+    pub fn set_client(&mut self, client: OllamaClient) {
+        self.client = client;
+    }
+
+    pub fn send(&self) -> Result<GenerateResponse, OllamaError> {
+        self.client.generate(self)
+    }
+}
+
+/// A reply from the generate endpoint. Durations are reported by Ollama in nanoseconds.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GenerateResponse {
+    pub model: String,
+    pub created_at: Option<String>,
+    pub response: String,
+    #[serde(default)]
+    pub done: bool,
+    pub done_reason: Option<String>,
+    pub context: Option<Vec<i64>>,
+    pub total_duration: Option<u64>,
+    pub load_duration: Option<u64>,
+    pub prompt_eval_count: Option<u64>,
+    pub prompt_eval_duration: Option<u64>,
+    pub eval_count: Option<u64>,
+    pub eval_duration: Option<u64>,
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn should_only_send_the_fields_that_were_set() -> Result<(), serde_json::Error> {
+        let generate = Generate::new("llama3.2:1b-instruct-fp16", "Summarise my tasks")
+            .system("You summarise todo lists")
+            .keep_alive("5m");
+
+        assert_eq!(
+            serde_json::to_string(&generate)?,
+            r#"{"model":"llama3.2:1b-instruct-fp16","prompt":"Summarise my tasks","system":"You summarise todo lists","keep_alive":"5m","stream":false}"#
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_parse_a_generate_response() -> Result<(), serde_json::Error> {
+        let body = r#"{"model":"llama3.2:1b-instruct-fp16","created_at":"2024-11-20T10:00:00Z","response":"You have 3 tasks.","done":true,"done_reason":"stop","context":[1,2,3],"total_duration":10706818083,"eval_count":290,"eval_duration":4709213000}"#;
+
+        let response = serde_json::from_str::<GenerateResponse>(body)?;
+
+        assert_eq!(response.response, "You have 3 tasks.");
+        assert_eq!(response.context, Some(vec![1, 2, 3]));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+use super::{
+    message::{Message, Role},
+    tool::Tool,
+};
+
+/// Marks the system message holding the summary of dropped turns, so that the next summary can
+/// fold it in instead of keeping it pinned forever.
+pub const SUMMARY_PREFIX: &str = "Summary of the earlier conversation:";
+
+/// The tokens kept free for the summary, the summary prompt asks for one well under this size.
+pub const SUMMARY_TOKENS: usize = 200;
+
+/// How much of a chat's history is sent with each request. System messages are always kept, and
+/// the oldest of the other messages are dropped until the history fits both limits. Dropped
+/// messages can be summarised by a model into a single system message.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryPolicy {
+    pub max_messages: Option<usize>,
+    pub max_tokens: Option<usize>,
+    /// The model to summarise dropped messages with, they are forgotten when this is `None`.
+    pub summary_model: Option<String>,
+}
+
+impl HistoryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_messages(mut self, max_messages: usize) -> Self {
+        self.max_messages = Some(max_messages);
+
+        self
+    }
+
+    /// Caps the history by its estimated size, see `estimate_tokens`.
+    pub fn max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = Some(max_tokens);
+
+        self
+    }
+
+    pub fn summarize_with(mut self, model: impl Into<String>) -> Self {
+        self.summary_model = Some(model.into());
+
+        self
+    }
+
+    /// Splits the messages into the ones to keep sending and the ones to drop, both in their
+    /// original order. Nothing is dropped until the history goes over a limit, then it's cut down
+    /// to half of the limits so that the next few sends don't need trimming (or a summary) again.
+    /// Only whole turns are dropped, so a tool result never loses the message with the call it
+    /// answers. The newest message is always kept.
+    pub fn split(&self, messages: Vec<Message>) -> (Vec<Message>, Vec<Message>) {
+        let (pinned, history): (Vec<Message>, Vec<Message>) =
+            messages.into_iter().partition(is_pinned);
+
+        let pinned_tokens = pinned.iter().map(estimate_tokens).sum::<usize>();
+        let history_tokens = history.iter().map(estimate_tokens).sum::<usize>();
+        // leave room for the summary message that replaces whatever is dropped
+        let summarizes = self.summary_model.is_some();
+        let reserved_messages = usize::from(summarizes);
+        let reserved_tokens = if summarizes { SUMMARY_TOKENS } else { 0 };
+        let message_limit = self
+            .max_messages
+            .map(|max| max.saturating_sub(pinned.len() + reserved_messages));
+        let token_limit = self
+            .max_tokens
+            .map(|max| max.saturating_sub(pinned_tokens + reserved_tokens));
+
+        let over_limit = message_limit.is_some_and(|limit| history.len() > limit)
+            || token_limit.is_some_and(|limit| history_tokens > limit);
+
+        if !over_limit {
+            return (pinned.into_iter().chain(history).collect(), vec![]);
+        }
+
+        let mut message_budget = message_limit.map(|limit| limit.div_ceil(2));
+        let mut token_budget = token_limit.map(|limit| limit / 2);
+        let mut keep_from = history.len();
+
+        for (index, message) in history.iter().enumerate().rev() {
+            let tokens = estimate_tokens(message);
+            let fits = message_budget.is_none_or(|budget| budget >= 1)
+                && token_budget.is_none_or(|budget| budget >= tokens);
+
+            if !fits && keep_from < history.len() {
+                break;
+            }
+
+            message_budget = message_budget.map(|budget| budget.saturating_sub(1));
+            token_budget = token_budget.map(|budget| budget.saturating_sub(tokens));
+            keep_from = index;
+        }
+
+        while keep_from + 1 < history.len() && history[keep_from].role == Role::Tool {
+            keep_from += 1;
+        }
+
+        let mut history = history;
+        let kept = history.split_off(keep_from);
+
+        (pinned.into_iter().chain(kept).collect(), history)
+    }
+}
+
+/// A rough count of the tokens a message takes up, about four characters per token plus a little
+/// for the role and any tool calls.
+pub fn estimate_tokens(message: &Message) -> usize {
+    let tool_calls = message
+        .tool_calls
+        .as_ref()
+        .map(|tool_calls| serde_json::to_string(tool_calls).unwrap_or_default().len())
+        .unwrap_or_default();
+
+    (message.content.len() + tool_calls).div_ceil(4) + 4
+}
+
+/// A rough count of the tokens a tool definition takes up. Every tool is sent with every request,
+/// so this comes out of the context window before any of the history does.
+pub fn estimate_tool_tokens(tool: &Tool) -> usize {
+    serde_json::to_string(tool)
+        .unwrap_or_default()
+        .len()
+        .div_ceil(4)
+}
+
+/// The prompt asking a model to summarise messages that are about to be dropped.
+pub(crate) fn summary_prompt(dropped: &[Message]) -> String {
+    let transcript = dropped
+        .iter()
+        .map(|message| {
+            let content = message
+                .content
+                .strip_prefix(SUMMARY_PREFIX)
+                .unwrap_or(&message.content)
+                .trim();
+
+            format!("{:?}: {content}", message.role)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!("Summarise the following conversation in under 100 words. Keep any names, ids, decisions and open questions so the conversation can continue without it.\n\n{transcript}")
+}
+
+fn is_pinned(message: &Message) -> bool {
+    message.role == Role::System && !message.content.starts_with(SUMMARY_PREFIX)
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(dead_code)]
+    fn contents(messages: &[Message]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect()
+    }
+
+    #[allow(dead_code)]
+    fn conversation() -> Vec<Message> {
+        vec![
+            Message::new_system("You manage a todo list"),
+            Message::new_user("one"),
+            Message {
+                role: Role::Assistant,
+                content: "two".to_owned(),
+                ..Default::default()
+            },
+            Message::new_tool("three"),
+            Message::new_user("four"),
+        ]
+    }
+
+    #[test]
+    fn should_keep_everything_until_the_history_is_over_the_limit() {
+        let (kept, dropped) = HistoryPolicy::new().max_messages(5).split(conversation());
+
+        assert_eq!(kept.len(), 5);
+        assert!(dropped.is_empty());
+    }
+
+    #[test]
+    fn should_keep_system_messages_and_trim_to_half_the_limit() {
+        let mut messages = conversation();
+        messages.push(Message::new_user("five"));
+        messages.push(Message::new_user("six"));
+
+        let (kept, dropped) = HistoryPolicy::new().max_messages(6).split(messages);
+
+        assert_eq!(
+            contents(&kept),
+            vec!["You manage a todo list", "four", "five", "six"]
+        );
+        assert_eq!(contents(&dropped), vec!["one", "two", "three"]);
+    }
+
+    #[test]
+    fn should_not_keep_a_tool_result_without_its_call() {
+        let (kept, dropped) = HistoryPolicy::new()
+            .max_messages(4)
+            .summarize_with("llama3.2")
+            .split(conversation());
+
+        assert_eq!(contents(&kept), vec!["You manage a todo list", "four"]);
+        assert_eq!(contents(&dropped), vec!["one", "two", "three"]);
+    }
+
+    #[test]
+    fn should_cap_the_history_by_estimated_tokens() {
+        let mut messages = conversation();
+        messages.insert(1, Message::new_user("a".repeat(400)));
+
+        let (kept, dropped) = HistoryPolicy::new().max_tokens(60).split(messages);
+
+        assert_eq!(kept.len(), 5);
+        assert_eq!(contents(&dropped), vec!["a".repeat(400)]);
+    }
+
+    #[test]
+    fn should_fold_an_old_summary_into_the_next_one() {
+        let mut messages = conversation();
+        messages.insert(1, Message::new_system(format!("{SUMMARY_PREFIX} we met")));
+
+        let (kept, dropped) = HistoryPolicy::new().max_messages(2).split(messages);
+
+        assert_eq!(contents(&kept), vec!["You manage a todo list", "four"]);
+        assert!(summary_prompt(&dropped).contains("System: we met\nUser: one"));
+    }
+}
@@ -0,0 +1,27 @@
+use std::{fs, io, path::Path};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// Encodes raw image bytes the way Ollama expects images to be sent to vision models.
+pub fn encode_image(bytes: impl AsRef<[u8]>) -> String {
+    STANDARD.encode(bytes)
+}
+
+pub fn encode_image_file(path: impl AsRef<Path>) -> io::Result<String> {
+    Ok(encode_image(fs::read(path)?))
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn should_base64_encode_images() {
+        assert_eq!(encode_image(b"sticky note"), "c3RpY2t5IG5vdGU=");
+    }
+
+    #[test]
+    fn should_fail_to_encode_missing_files() {
+        assert!(encode_image_file("./does/not/exist.png").is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+
+/// A model that has been pulled onto the Ollama server, as listed by `/api/tags`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct LocalModel {
+    pub name: String,
+    pub model: String,
+    pub modified_at: Option<String>,
+    pub size: u64,
+    pub digest: String,
+    #[serde(default)]
+    pub details: ModelDetails,
+}
+
+impl LocalModel {
+    /// Whether this is the model the user asked for. Ollama fills in the `latest` tag when a
+    /// model is named without one, so `llama3.2` matches `llama3.2:latest`.
+    pub fn matches(&self, name: &str) -> bool {
+        with_default_tag(&self.name) == with_default_tag(name)
+    }
+}
+
+fn with_default_tag(name: &str) -> String {
+    if name.contains(':') {
+        name.to_owned()
+    } else {
+        format!("{name}:latest")
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ModelDetails {
+    pub parent_model: Option<String>,
+    pub format: Option<String>,
+    pub family: Option<String>,
+    pub families: Option<Vec<String>>,
+    pub parameter_size: Option<String>,
+    pub quantization_level: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelList {
+    pub models: Vec<LocalModel>,
+}
+
+/// Everything `/api/show` knows about a single model.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub modelfile: Option<String>,
+    pub parameters: Option<String>,
+    pub template: Option<String>,
+    pub license: Option<String>,
+    #[serde(default)]
+    pub details: ModelDetails,
+    pub model_info: Option<serde_json::Map<String, serde_json::Value>>,
+    pub capabilities: Option<Vec<String>>,
+}
+
+/// A model currently loaded into memory, as listed by `/api/ps`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunningModel {
+    pub name: String,
+    pub model: String,
+    pub size: u64,
+    pub digest: String,
+    #[serde(default)]
+    pub details: ModelDetails,
+    pub expires_at: Option<String>,
+    pub size_vram: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunningModelList {
+    pub models: Vec<RunningModel>,
+}
+
+/// One update from a streaming `/api/pull`. While layers download `total` and `completed` are
+/// set, and the last update has a status of `success`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct PullProgress {
+    pub status: String,
+    pub digest: Option<String>,
+    pub total: Option<u64>,
+    pub completed: Option<u64>,
+}
+
+impl PullProgress {
+    pub fn percent(&self) -> Option<f64> {
+        let total = self.total.filter(|total| *total > 0)?;
+
+        Some(self.completed.unwrap_or_default() as f64 / total as f64 * 100.0)
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status == "success"
+    }
+}
+
+/// The body for the model endpoints that only need to know which model to act on.
+#[derive(Debug, Serialize)]
+pub(crate) struct ModelRequest<'a> {
+    pub model: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn should_parse_the_list_of_local_models() -> Result<(), serde_json::Error> {
+        let body = r#"{"models":[{"name":"llama3.2:1b-instruct-fp16","model":"llama3.2:1b-instruct-fp16","modified_at":"2024-11-20T10:00:00.000000-07:00","size":2479595104,"digest":"a01cf6c8","details":{"parent_model":"","format":"gguf","family":"llama","families":["llama"],"parameter_size":"1.2B","quantization_level":"F16"}}]}"#;
+
+        let list = serde_json::from_str::<ModelList>(body)?;
+
+        assert_eq!(list.models.len(), 1);
+        assert_eq!(
+            list.models[0].details.parameter_size.as_deref(),
+            Some("1.2B")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_match_models_with_and_without_the_latest_tag() {
+        let model = LocalModel {
+            name: "llama3.2:latest".to_owned(),
+            ..Default::default()
+        };
+
+        assert!(model.matches("llama3.2"));
+        assert!(model.matches("llama3.2:latest"));
+        assert!(!model.matches("llama3.2:1b"));
+    }
+
+    #[test]
+    fn should_report_pull_progress_as_a_percentage() {
+        let downloading = PullProgress {
+            status: "pulling 6a0746a1ec1a".to_owned(),
+            total: Some(200),
+            completed: Some(50),
+            ..Default::default()
+        };
+        let verifying = PullProgress {
+            status: "verifying sha256 digest".to_owned(),
+            ..Default::default()
+        };
+
+        assert_eq!(downloading.percent(), Some(25.0));
+        assert_eq!(verifying.percent(), None);
+    }
+}
@@ -1,50 +1,97 @@
-use std::fmt::Display;
+use std::{fmt::Display, io, path::Path};
 
 use serde::{Deserialize, Serialize};
 
-use super::tool_call::ToolCall;
+use super::{
+    image::{encode_image, encode_image_file},
+    tool_call::ToolCall,
+};
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Message {
     pub role: Role,
     pub content: String,
     pub tool_calls: Option<Vec<ToolCall>>,
+    /// Base64 encoded images, only understood by vision models such as llama3.2-vision.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<String>>,
+    /// On a tool message, the name of the tool that produced it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
 }
 
 impl Message {
     pub fn new_user(content: impl Into<String>) -> Self {
         let role = Role::User;
         let tool_calls = None;
+        let images = None;
+        let tool_name = None;
 
         Self {
             role,
             content: content.into(),
             tool_calls,
+            images,
+            tool_name,
         }
     }
 
     pub fn new_tool(content: impl Into<String>) -> Self {
         let role = Role::Tool;
         let tool_calls = None;
+        let images = None;
+        let tool_name = None;
 
         Self {
             role,
             content: content.into(),
             tool_calls,
+            images,
+            tool_name,
+        }
+    }
+
+    /// The answer to a tool call, named after the tool so the model can tell which result belongs
+    /// to which call when it made several. Ollama matches results to calls by `tool_name`.
+    pub fn new_tool_result(tool_call: &ToolCall, content: impl Into<String>) -> Self {
+        Self {
+            tool_name: Some(tool_call.function.name.clone()),
+            ..Self::new_tool(content)
         }
     }
 
     pub fn new_system(content: impl ToString) -> Self {
         let role = Role::System;
         let tool_calls = None;
+        let images = None;
+        let tool_name = None;
 
         Self {
             role,
             content: content.to_string(),
             tool_calls,
+            images,
+            tool_name,
         }
     }
 
+    /// Attaches an image from its raw bytes, for example a photo straight from a camera.
+    pub fn with_image_bytes(mut self, bytes: impl AsRef<[u8]>) -> Self {
+        self.images
+            .get_or_insert_with(Vec::new)
+            .push(encode_image(bytes));
+
+        self
+    }
+
+    pub fn with_image_file(mut self, path: impl AsRef<Path>) -> io::Result<Self> {
+        self.images
+            .get_or_insert_with(Vec::new)
+            .push(encode_image_file(path)?);
+
+        Ok(self)
+    }
+
     pub fn prepend_content(&mut self, message: &str) {
         self.content.insert_str(0, message);
     }
@@ -78,4 +125,40 @@ mod tests {
 
         assert_eq!(message.content, "hello world");
     }
+
+    #[test]
+    fn should_serialize_images_only_when_attached() -> Result<(), serde_json::Error> {
+        let text = serde_json::to_value(Message::new_user("Turn this into tasks"))?;
+        let photo = serde_json::to_value(
+            Message::new_user("Turn this into tasks").with_image_bytes(b"sticky note"),
+        )?;
+
+        assert!(text.get("images").is_none());
+        assert_eq!(photo["images"], serde_json::json!(["c3RpY2t5IG5vdGU="]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_name_tool_results_after_their_tool() -> Result<(), serde_json::Error> {
+        let tool_call = serde_json::from_str::<ToolCall>(
+            r#"{"id":"call_1","function":{"name":"get_all_tasks_from_db","arguments":{}}}"#,
+        )?;
+
+        let result = serde_json::to_value(Message::new_tool_result(&tool_call, "[]"))?;
+        let plain = serde_json::to_value(Message::new_tool("[]"))?;
+
+        assert_eq!(
+            result,
+            serde_json::json!({
+                "role": "tool",
+                "content": "[]",
+                "tool_calls": null,
+                "tool_name": "get_all_tasks_from_db"
+            })
+        );
+        assert!(plain.get("tool_name").is_none());
+
+        Ok(())
+    }
 }
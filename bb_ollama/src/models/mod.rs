@@ -1,5 +1,12 @@
 pub mod chat_request;
+pub mod embed;
+pub mod format;
+pub mod generate_request;
+pub mod history;
+pub mod image;
+pub mod local_model;
 pub mod message;
 pub mod options;
 pub mod tool;
 pub mod tool_call;
+pub mod usage;
@@ -1,11 +1,13 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+/// Settings for how a `Chat` behaves. Only `model_options` are sent to Ollama as the `options`
+/// field, the system prompt becomes the first message and `save_messages` never leaves the crate.
+#[derive(Debug, Default, Clone)]
 pub struct ChatRequestOptions {
     pub system: Option<String>,
-    pub seed: Option<u32>,
     pub save_messages: bool,
-    pub temperature: f32,
+    pub keep_alive: Option<String>,
+    pub model_options: ModelOptions,
 }
 
 impl ChatRequestOptions {
@@ -14,7 +16,7 @@ impl ChatRequestOptions {
     }
 
     pub fn seed(mut self, seed: u32) -> Self {
-        self.seed = Some(seed);
+        self.model_options.seed = Some(seed);
 
         self
     }
@@ -32,7 +34,296 @@ impl ChatRequestOptions {
     }
 
     pub fn temperature(mut self, temperature: f32) -> Self {
-        self.temperature = temperature;
+        self.model_options.temperature = Some(temperature);
+        self
+    }
+
+    /// The size of the context window in tokens, Ollama falls back to a small default otherwise.
+    pub fn num_ctx(mut self, num_ctx: u32) -> Self {
+        self.model_options.num_ctx = Some(num_ctx);
+
+        self
+    }
+
+    /// How long Ollama should keep the model loaded after a request, for example `"5m"`.
+    pub fn keep_alive(mut self, keep_alive: impl Into<String>) -> Self {
+        self.keep_alive = Some(keep_alive.into());
+
+        self
+    }
+
+    pub fn model_options(mut self, model_options: ModelOptions) -> Self {
+        self.model_options = model_options;
+
+        self
+    }
+}
+
+/// The model parameters Ollama accepts in the `options` field. Anything left unset is not sent,
+/// so the model's own defaults from its Modelfile apply.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_keep: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u32>,
+    /// The most tokens to generate, `-1` for no limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub typical_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_last_n: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    /// `0` disables mirostat sampling, `1` and `2` pick the version.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirostat: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirostat_tau: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirostat_eta: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub penalize_newline: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub numa: Option<bool>,
+    /// The size of the context window in tokens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_batch: Option<u32>,
+    /// How many layers to offload to the GPU, `0` to run on the CPU only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_gpu: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub main_gpu: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub low_vram: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vocab_only: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub use_mmap: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub use_mlock: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_thread: Option<u32>,
+}
+
+impl ModelOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether nothing has been set, in which case the `options` field can be left out.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn num_keep(mut self, num_keep: i32) -> Self {
+        self.num_keep = Some(num_keep);
+
+        self
+    }
+
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.seed = Some(seed);
+
+        self
+    }
+
+    pub fn num_predict(mut self, num_predict: i32) -> Self {
+        self.num_predict = Some(num_predict);
+
+        self
+    }
+
+    pub fn top_k(mut self, top_k: u32) -> Self {
+        self.top_k = Some(top_k);
+
+        self
+    }
+
+    pub fn top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+
+        self
+    }
+
+    pub fn min_p(mut self, min_p: f32) -> Self {
+        self.min_p = Some(min_p);
+
+        self
+    }
+
+    pub fn typical_p(mut self, typical_p: f32) -> Self {
+        self.typical_p = Some(typical_p);
+
+        self
+    }
+
+    pub fn repeat_last_n(mut self, repeat_last_n: i32) -> Self {
+        self.repeat_last_n = Some(repeat_last_n);
+
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+
+        self
+    }
+
+    pub fn repeat_penalty(mut self, repeat_penalty: f32) -> Self {
+        self.repeat_penalty = Some(repeat_penalty);
+
+        self
+    }
+
+    pub fn presence_penalty(mut self, presence_penalty: f32) -> Self {
+        self.presence_penalty = Some(presence_penalty);
+
+        self
+    }
+
+    pub fn frequency_penalty(mut self, frequency_penalty: f32) -> Self {
+        self.frequency_penalty = Some(frequency_penalty);
+
+        self
+    }
+
+    pub fn mirostat(mut self, mirostat: u8) -> Self {
+        self.mirostat = Some(mirostat);
+
+        self
+    }
+
+    pub fn mirostat_tau(mut self, mirostat_tau: f32) -> Self {
+        self.mirostat_tau = Some(mirostat_tau);
+
+        self
+    }
+
+    pub fn mirostat_eta(mut self, mirostat_eta: f32) -> Self {
+        self.mirostat_eta = Some(mirostat_eta);
+
+        self
+    }
+
+    pub fn penalize_newline(mut self, penalize_newline: bool) -> Self {
+        self.penalize_newline = Some(penalize_newline);
+
+        self
+    }
+
+    pub fn add_stop(mut self, stop: impl Into<String>) -> Self {
+        self.stop.get_or_insert_with(Vec::new).push(stop.into());
+
+        self
+    }
+
+    pub fn numa(mut self, numa: bool) -> Self {
+        self.numa = Some(numa);
+
+        self
+    }
+
+    pub fn num_ctx(mut self, num_ctx: u32) -> Self {
+        self.num_ctx = Some(num_ctx);
+
+        self
+    }
+
+    pub fn num_batch(mut self, num_batch: u32) -> Self {
+        self.num_batch = Some(num_batch);
+
+        self
+    }
+
+    pub fn num_gpu(mut self, num_gpu: i32) -> Self {
+        self.num_gpu = Some(num_gpu);
+
+        self
+    }
+
+    pub fn main_gpu(mut self, main_gpu: u32) -> Self {
+        self.main_gpu = Some(main_gpu);
+
+        self
+    }
+
+    pub fn low_vram(mut self, low_vram: bool) -> Self {
+        self.low_vram = Some(low_vram);
+
+        self
+    }
+
+    pub fn vocab_only(mut self, vocab_only: bool) -> Self {
+        self.vocab_only = Some(vocab_only);
+
+        self
+    }
+
+    pub fn use_mmap(mut self, use_mmap: bool) -> Self {
+        self.use_mmap = Some(use_mmap);
+
+        self
+    }
+
+    pub fn use_mlock(mut self, use_mlock: bool) -> Self {
+        self.use_mlock = Some(use_mlock);
+
+        self
+    }
+
+    pub fn num_thread(mut self, num_thread: u32) -> Self {
+        self.num_thread = Some(num_thread);
+
         self
     }
 }
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn should_only_serialize_the_options_that_were_set() -> Result<(), serde_json::Error> {
+        let options = ModelOptions::new()
+            .num_ctx(8192)
+            .temperature(0.5)
+            .add_stop("</done>");
+
+        assert_eq!(
+            serde_json::to_string(&options)?,
+            r#"{"temperature":0.5,"stop":["</done>"],"num_ctx":8192}"#
+        );
+        assert!(ModelOptions::new().is_empty());
+        assert!(!options.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_pass_model_parameters_through_to_the_model_options() {
+        let options = ChatRequestOptions::new().seed(123).temperature(0.7);
+
+        assert_eq!(
+            options.model_options,
+            ModelOptions::new().seed(123).temperature(0.7)
+        );
+    }
+}
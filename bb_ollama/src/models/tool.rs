@@ -1,7 +1,14 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, fmt::Display};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::lenient::Lenient;
+
+use super::tool_call::{Arguments, ToolCall};
+
+pub use bb_ollama_derive::OllamaTool;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tool {
     #[serde(rename = "type")]
     pub tool_type: String,
@@ -9,6 +16,7 @@ pub struct Tool {
 }
 
 impl Tool {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new() -> ToolBuilder {
         ToolBuilder::new()
     }
@@ -20,6 +28,7 @@ pub struct ToolBuilder {
     pub function_description: Option<String>,
     pub properties: HashMap<String, Property>,
     pub required_properties: Vec<String>,
+    duplicate_properties: Vec<String>,
 }
 
 impl ToolBuilder {
@@ -40,7 +49,11 @@ impl ToolBuilder {
     }
 
     pub fn add_function_property(mut self, name: impl ToString, property: Property) -> Self {
-        self.properties.insert(name.to_string(), property);
+        let name = name.to_string();
+
+        if self.properties.insert(name.clone(), property).is_some() {
+            self.duplicate_properties.push(name);
+        }
 
         self
     }
@@ -51,36 +64,120 @@ impl ToolBuilder {
         self
     }
 
-    pub fn build(self) -> Tool {
-        let tool = Tool {
+    /// Checks the tool is one Ollama and the model can use before building it.
+    pub fn build(self) -> Result<Tool, ToolError> {
+        let name = self.function_name.ok_or(ToolError::MissingName)?;
+
+        if !is_valid_function_name(&name) {
+            return Err(ToolError::InvalidName(name));
+        }
+
+        let description = self
+            .function_description
+            .filter(|description| !description.trim().is_empty())
+            .ok_or_else(|| ToolError::MissingDescription(name.clone()))?;
+
+        if let Some(property) = self.duplicate_properties.into_iter().next() {
+            return Err(ToolError::DuplicateProperty {
+                tool: name,
+                property,
+            });
+        }
+
+        for (index, property) in self.required_properties.iter().enumerate() {
+            if !self.properties.contains_key(property) {
+                return Err(ToolError::UnknownRequiredProperty {
+                    tool: name,
+                    property: property.clone(),
+                });
+            }
+
+            if self.required_properties[..index].contains(property) {
+                return Err(ToolError::DuplicateProperty {
+                    tool: name,
+                    property: property.clone(),
+                });
+            }
+        }
+
+        Ok(Tool {
             tool_type: "function".to_owned(),
             function: Function {
-                name: self
-                    .function_name
-                    .expect("Missing function name when building tool"),
-                description: self
-                    .function_description
-                    .expect("Missing description when building tool"),
+                name,
+                description,
                 parameters: Parameter {
                     parameter_type: "object".to_owned(),
                     properties: self.properties,
                     required: self.required_properties,
                 },
             },
-        };
+        })
+    }
+}
 
-        tool
+/// Function names may hold letters, digits, underscores and dashes, start with a letter or an
+/// underscore, and be at most 64 characters long.
+fn is_valid_function_name(name: &str) -> bool {
+    let mut characters = name.chars();
+
+    name.len() <= 64
+        && characters
+            .next()
+            .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && characters.all(|character| {
+            character.is_ascii_alphanumeric() || character == '_' || character == '-'
+        })
+}
+
+/// Why a tool could not be built or added to a chat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolError {
+    MissingName,
+    MissingDescription(String),
+    InvalidName(String),
+    UnknownRequiredProperty {
+        tool: String,
+        property: String,
+    },
+    DuplicateProperty {
+        tool: String,
+        property: String,
+    },
+    /// A chat already has a tool with this name.
+    DuplicateTool(String),
+}
+
+impl Display for ToolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingName => write!(f, "the tool is missing a function name"),
+            Self::MissingDescription(tool) => write!(f, "the tool '{tool}' is missing a description"),
+            Self::InvalidName(tool) => write!(
+                f,
+                "'{tool}' is not a valid tool name, use up to 64 letters, digits, underscores and dashes, starting with a letter or underscore"
+            ),
+            Self::UnknownRequiredProperty { tool, property } => write!(
+                f,
+                "the tool '{tool}' requires the property '{property}' but doesn't have it"
+            ),
+            Self::DuplicateProperty { tool, property } => {
+                write!(f, "the tool '{tool}' has the property '{property}' more than once")
+            }
+            Self::DuplicateTool(tool) => write!(f, "a tool named '{tool}' was already added"),
+        }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl std::error::Error for ToolError {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Function {
     pub name: String,
     pub description: String,
     pub parameters: Parameter,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Parameter {
     #[serde(rename = "type")]
     pub parameter_type: String,
@@ -88,35 +185,408 @@ pub struct Parameter {
     pub required: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The schema of a single tool argument, a subset of JSON-Schema. Numbers can be bounded, any
+/// type can be limited to a list of allowed values, arrays describe their items and objects
+/// carry their own properties and required list.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Property {
     #[serde(rename = "type")]
     pub property_type: PropertyType,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub description: String,
+    #[serde(rename = "enum", skip_serializing_if = "Option::is_none")]
+    pub allowed_values: Option<Vec<Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minimum: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maximum: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub items: Option<Box<Property>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub properties: Option<HashMap<String, Property>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+    /// A hint such as `date-time` or `email` for what the string holds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
 }
 
 impl Property {
-    pub fn new_string(description: impl Into<String>) -> Self {
+    pub fn new(property_type: PropertyType, description: impl Into<String>) -> Self {
         Self {
-            property_type: PropertyType::String,
+            property_type,
             description: description.into(),
+            ..Default::default()
         }
     }
 
+    pub fn new_string(description: impl Into<String>) -> Self {
+        Self::new(PropertyType::String, description)
+    }
+
     pub fn new_bool(description: impl Into<String>) -> Self {
+        Self::new(PropertyType::Boolean, description)
+    }
+
+    pub fn new_integer(description: impl Into<String>) -> Self {
+        Self::new(PropertyType::Integer, description)
+    }
+
+    pub fn new_number(description: impl Into<String>) -> Self {
+        Self::new(PropertyType::Number, description)
+    }
+
+    pub fn new_array(description: impl Into<String>, items: Property) -> Self {
         Self {
-            property_type: PropertyType::String,
-            description: format!(
-                "{}. Note Be sure to set this property as a stringified version of a boolean",
-                description.into()
-            ),
+            items: Some(Box::new(items)),
+            ..Self::new(PropertyType::Array, description)
+        }
+    }
+
+    pub fn new_object(description: impl Into<String>) -> Self {
+        Self {
+            properties: Some(HashMap::new()),
+            required: Some(vec![]),
+            ..Self::new(PropertyType::Object, description)
         }
     }
+
+    pub fn minimum(mut self, minimum: impl Into<f64>) -> Self {
+        self.minimum = Some(minimum.into());
+
+        self
+    }
+
+    pub fn maximum(mut self, maximum: impl Into<f64>) -> Self {
+        self.maximum = Some(maximum.into());
+
+        self
+    }
+
+    pub fn allowed_values<T: Into<Value>>(mut self, values: impl IntoIterator<Item = T>) -> Self {
+        self.allowed_values = Some(values.into_iter().map(Into::into).collect());
+
+        self
+    }
+
+    pub fn default_value(mut self, default: impl Into<Value>) -> Self {
+        self.default = Some(default.into());
+
+        self
+    }
+
+    pub fn format(mut self, format: impl Into<String>) -> Self {
+        self.format = Some(format.into());
+
+        self
+    }
+
+    /// Adds a property to a nested object.
+    pub fn add_property(mut self, name: impl ToString, property: Property) -> Self {
+        self.properties
+            .get_or_insert_with(HashMap::new)
+            .insert(name.to_string(), property);
+
+        self
+    }
+
+    /// Marks a property of a nested object as required.
+    pub fn add_required_property(mut self, property_name: impl ToString) -> Self {
+        self.required
+            .get_or_insert_with(Vec::new)
+            .push(property_name.to_string());
+
+        self
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum PropertyType {
+    #[default]
     String,
-    Bool,
+    Integer,
+    Number,
+    Boolean,
+    Array,
+    Object,
+}
+
+/// A tool described by a Rust struct, usually through `#[derive(OllamaTool)]`, so the definition
+/// sent to the model and the parsing of its arguments can't drift apart.
+pub trait OllamaTool: Sized {
+    fn name() -> &'static str;
+
+    fn tool() -> Result<Tool, ToolError>;
+
+    fn from_arguments(arguments: &Arguments) -> Result<Self, serde_json::Error>;
+
+    fn from_tool_call(tool_call: &ToolCall) -> Result<Self, serde_json::Error> {
+        Self::from_arguments(&tool_call.function.arguments)
+    }
+}
+
+/// A Rust type that can be a tool argument, with the schema the model is shown for it.
+pub trait ToolArgument: DeserializeOwned {
+    const REQUIRED: bool = true;
+
+    fn property(description: &str) -> Property;
+}
+
+macro_rules! tool_argument {
+    ($constructor:ident: $($argument:ty),+) => {
+        $(impl ToolArgument for $argument {
+            fn property(description: &str) -> Property {
+                Property::$constructor(description)
+            }
+        })+
+    };
+}
+
+tool_argument!(new_string: String);
+tool_argument!(new_bool: bool);
+tool_argument!(new_integer: i8, i16, i32, i64, isize);
+tool_argument!(new_number: f32, f64);
+
+macro_rules! unsigned_tool_argument {
+    ($($argument:ty),+) => {
+        $(impl ToolArgument for $argument {
+            fn property(description: &str) -> Property {
+                Property::new_integer(description).minimum(0)
+            }
+        })+
+    };
+}
+
+unsigned_tool_argument!(u8, u16, u32, u64, usize);
+
+impl<T: ToolArgument> ToolArgument for Option<T> {
+    const REQUIRED: bool = false;
+
+    fn property(description: &str) -> Property {
+        T::property(description)
+    }
+}
+
+impl<T: ToolArgument> ToolArgument for Vec<T> {
+    fn property(description: &str) -> Property {
+        Property::new_array(description, T::property(""))
+    }
+}
+
+/// Reads a single argument leniently, see `ToolCall::parse_arguments`. Missing arguments are
+/// `None` for optional types and an error for everything else.
+pub fn argument<T: DeserializeOwned>(
+    arguments: &Arguments,
+    name: &'static str,
+) -> Result<T, serde_json::Error> {
+    match arguments.get(name) {
+        Some(value) => T::deserialize(Lenient(value)),
+        None => {
+            T::deserialize(Lenient(&Value::Null)).map_err(|_| serde::de::Error::missing_field(name))
+        }
+    }
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use serde_json::json;
+
+    /// Update a task in the database.
+    #[allow(dead_code)]
+    #[derive(Debug, OllamaTool)]
+    #[tool(name = "update_task_in_db")]
+    struct UpdateTask {
+        /// The id of the task.
+        id: i32,
+        /// A new name for
+        /// the task.
+        #[tool(rename = "name")]
+        new_name: Option<String>,
+        #[tool(minimum = 1, maximum = 5)]
+        priority: Option<u8>,
+        tags: Vec<String>,
+    }
+
+    #[allow(dead_code)]
+    /// Quit the application.
+    #[derive(Debug, OllamaTool)]
+    struct Quit;
+
+    #[test]
+    fn should_describe_booleans_as_booleans() -> Result<(), serde_json::Error> {
+        assert_eq!(
+            serde_json::to_value(Property::new_bool("Whether the task is done"))?,
+            json!({ "type": "boolean", "description": "Whether the task is done" })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_serialize_a_nested_schema() -> Result<(), serde_json::Error> {
+        let task = Property::new_object("A task to add")
+            .add_property("name", Property::new_string("The name of the task"))
+            .add_property(
+                "priority",
+                Property::new_integer("How important the task is")
+                    .minimum(1)
+                    .maximum(5)
+                    .default_value(3),
+            )
+            .add_property(
+                "tags",
+                Property::new_array(
+                    "Labels for the task",
+                    Property::new_string("").allowed_values(["home", "work"]),
+                ),
+            )
+            .add_required_property("name");
+
+        assert_eq!(
+            serde_json::to_value(task)?,
+            json!({
+                "type": "object",
+                "description": "A task to add",
+                "properties": {
+                    "name": { "type": "string", "description": "The name of the task" },
+                    "priority": {
+                        "type": "integer",
+                        "description": "How important the task is",
+                        "minimum": 1.0,
+                        "maximum": 5.0,
+                        "default": 3
+                    },
+                    "tags": {
+                        "type": "array",
+                        "description": "Labels for the task",
+                        "items": { "type": "string", "enum": ["home", "work"] }
+                    }
+                },
+                "required": ["name"]
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_derive_a_tool_from_a_struct() -> Result<(), serde_json::Error> {
+        let tool = serde_json::to_value(UpdateTask::tool().unwrap())?;
+
+        assert_eq!(tool["function"]["name"], "update_task_in_db");
+        assert_eq!(
+            tool["function"]["description"],
+            "Update a task in the database."
+        );
+        assert_eq!(
+            tool["function"]["parameters"]["properties"],
+            json!({
+                "id": { "type": "integer", "description": "The id of the task." },
+                "name": { "type": "string", "description": "A new name for the task." },
+                "priority": { "type": "integer", "minimum": 1.0, "maximum": 5.0 },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            })
+        );
+        assert_eq!(
+            tool["function"]["parameters"]["required"],
+            json!(["id", "tags"])
+        );
+        assert_eq!(Quit::name(), "quit");
+        assert!(Quit::tool()
+            .unwrap()
+            .function
+            .parameters
+            .properties
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_parse_derived_tool_arguments() -> Result<(), serde_json::Error> {
+        let arguments = serde_json::from_str(r#"{"id":"4","name":"walk the dog","tags":[]}"#)?;
+
+        let update = UpdateTask::from_arguments(&arguments)?;
+
+        assert_eq!(update.id, 4);
+        assert_eq!(update.new_name.as_deref(), Some("walk the dog"));
+        assert_eq!(update.priority, None);
+
+        let error = UpdateTask::from_arguments(&Arguments::new()).unwrap_err();
+
+        assert_eq!(error.to_string(), "missing field `id`");
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_explain_why_a_tool_is_invalid() {
+        let property = || Property::new_string("The location");
+
+        assert_eq!(
+            Tool::new()
+                .function_description("Weather")
+                .build()
+                .unwrap_err(),
+            ToolError::MissingName
+        );
+        assert_eq!(
+            Tool::new()
+                .function_name("check_weather")
+                .build()
+                .unwrap_err(),
+            ToolError::MissingDescription("check_weather".to_owned())
+        );
+        assert_eq!(
+            Tool::new()
+                .function_name("check weather")
+                .function_description("Weather")
+                .build()
+                .unwrap_err(),
+            ToolError::InvalidName("check weather".to_owned())
+        );
+        assert_eq!(
+            Tool::new()
+                .function_name("check_weather")
+                .function_description("Weather")
+                .add_function_property("location", property())
+                .add_required_property("city")
+                .build()
+                .unwrap_err(),
+            ToolError::UnknownRequiredProperty {
+                tool: "check_weather".to_owned(),
+                property: "city".to_owned()
+            }
+        );
+        assert_eq!(
+            Tool::new()
+                .function_name("check_weather")
+                .function_description("Weather")
+                .add_function_property("location", property())
+                .add_function_property("location", property())
+                .build()
+                .unwrap_err(),
+            ToolError::DuplicateProperty {
+                tool: "check_weather".to_owned(),
+                property: "location".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn should_accept_a_valid_tool() {
+        let tool = Tool::new()
+            .function_name("check-weather_2")
+            .function_description("Weather")
+            .add_function_property("location", Property::new_string("The location"))
+            .add_required_property("location")
+            .build();
+
+        assert!(tool.is_ok());
+    }
 }
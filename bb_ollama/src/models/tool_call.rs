@@ -1,14 +1,84 @@
 use std::collections::HashMap;
 
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::lenient::Lenient;
+
+/// The arguments of a tool call by name, exactly as the model sent them.
+pub type Arguments = HashMap<String, Value>;
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct ToolCall {
+    /// Newer versions of Ollama give every call an id, results are still matched to calls by name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub function: ToolCallFunction,
 }
 
+impl ToolCall {
+    /// Reads the arguments into a typed struct. Models often quote numbers and booleans, so
+    /// `"3"` is accepted for an integer and `"true"` for a bool.
+    pub fn parse_arguments<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        let arguments = Value::Object(self.function.arguments.clone().into_iter().collect());
+
+        T::deserialize(Lenient(&arguments))
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct ToolCallFunction {
     pub name: String,
-    pub arguments: HashMap<String, String>,
+    #[serde(default)]
+    pub arguments: Arguments,
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize)]
+    struct UpdateTask {
+        id: i32,
+        name: Option<String>,
+        completed: Option<bool>,
+    }
+
+    #[test]
+    fn should_parse_arguments_whether_or_not_the_model_quoted_them() -> Result<(), serde_json::Error>
+    {
+        let quoted = serde_json::from_str::<ToolCall>(
+            r#"{"function":{"name":"update_task_in_db","arguments":{"id":"3","completed":"true"}}}"#,
+        )?;
+        let typed = serde_json::from_str::<ToolCall>(
+            r#"{"function":{"name":"update_task_in_db","arguments":{"id":3,"completed":true,"name":"walk the dog"}}}"#,
+        )?;
+
+        let quoted = quoted.parse_arguments::<UpdateTask>()?;
+        let typed = typed.parse_arguments::<UpdateTask>()?;
+
+        assert_eq!(
+            (quoted.id, quoted.completed, quoted.name),
+            (3, Some(true), None)
+        );
+        assert_eq!(typed.id, 3);
+        assert_eq!(typed.completed, Some(true));
+        assert_eq!(typed.name.as_deref(), Some("walk the dog"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_report_missing_arguments() -> Result<(), serde_json::Error> {
+        let tool_call = serde_json::from_str::<ToolCall>(
+            r#"{"function":{"name":"update_task_in_db","arguments":{}}}"#,
+        )?;
+
+        let error = tool_call.parse_arguments::<UpdateTask>().unwrap_err();
+
+        assert!(error.to_string().contains("missing field `id`"));
+
+        Ok(())
+    }
 }
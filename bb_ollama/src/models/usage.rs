@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use crate::api::{tokens_per_second, ChatResponse};
+
+/// Running totals of the work Ollama has done for a chat, across every request it has sent.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ChatUsage {
+    pub requests: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_duration: Duration,
+    pub eval_duration: Duration,
+}
+
+impl ChatUsage {
+    pub fn record(&mut self, response: &ChatResponse) {
+        self.requests += 1;
+        self.prompt_tokens += response.prompt_eval_count.unwrap_or_default();
+        self.completion_tokens += response.eval_count.unwrap_or_default();
+        self.total_duration += Duration::from_nanos(response.total_duration.unwrap_or_default());
+        self.eval_duration += Duration::from_nanos(response.eval_duration.unwrap_or_default());
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// The average generation speed over the whole session.
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        tokens_per_second(
+            self.completion_tokens,
+            self.eval_duration.as_nanos().try_into().ok()?,
+        )
+    }
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn should_add_up_usage_across_responses() {
+        let mut usage = ChatUsage::default();
+        let response = ChatResponse {
+            prompt_eval_count: Some(30),
+            eval_count: Some(10),
+            total_duration: Some(2_000_000_000),
+            eval_duration: Some(1_000_000_000),
+            ..Default::default()
+        };
+
+        usage.record(&response);
+        usage.record(&response);
+
+        assert_eq!(usage.requests, 2);
+        assert_eq!(usage.total_tokens(), 80);
+        assert_eq!(usage.total_duration, Duration::from_secs(4));
+        assert_eq!(usage.tokens_per_second(), Some(10.0));
+    }
+
+    #[test]
+    fn should_not_report_speed_before_anything_was_generated() {
+        assert_eq!(ChatUsage::default().tokens_per_second(), None);
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    hash::{BuildHasher, RandomState},
+    thread,
+    time::Duration,
+};
+
+use crate::error::OllamaError;
+
+/// How hard to try before giving up on a request to Ollama. Delays grow exponentially from
+/// `initial_backoff` up to `max_backoff`, with a random `jitter` fraction shaved off each one so
+/// that several clients don't retry in lockstep.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    pub jitter: f64,
+    pub retry_on: fn(&OllamaError) -> bool,
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends every request exactly once.
+    pub fn none() -> Self {
+        Self::new().max_attempts(1)
+    }
+
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+
+        self
+    }
+
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+
+        self
+    }
+
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+
+        self
+    }
+
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+
+        self
+    }
+
+    /// Decides which errors are worth retrying, by default `OllamaError::is_retryable`.
+    pub fn retry_on(mut self, retry_on: fn(&OllamaError) -> bool) -> Self {
+        self.retry_on = retry_on;
+
+        self
+    }
+
+    /// The delay before the given retry, where the first retry is `1`, before any jitter.
+    pub fn backoff_for(&self, retry: u32) -> Duration {
+        let factor = self.multiplier.powi(retry.saturating_sub(1) as i32);
+
+        self.initial_backoff.mul_f64(factor).min(self.max_backoff)
+    }
+
+    pub fn run<T>(
+        &self,
+        operation: impl FnMut() -> Result<T, OllamaError>,
+    ) -> Result<T, OllamaError> {
+        self.run_with_sleep(operation, thread::sleep)
+    }
+
+    fn run_with_sleep<T>(
+        &self,
+        mut operation: impl FnMut() -> Result<T, OllamaError>,
+        mut sleep: impl FnMut(Duration),
+    ) -> Result<T, OllamaError> {
+        let mut attempt = 1;
+
+        loop {
+            let error = match operation() {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+
+            if !(self.retry_on)(&error) {
+                return Err(error);
+            }
+
+            if attempt >= self.max_attempts {
+                return Err(OllamaError::RetriesExhausted {
+                    attempts: attempt,
+                    last_error: Box::new(error),
+                });
+            }
+
+            sleep(self.with_jitter(self.backoff_for(attempt)));
+            attempt += 1;
+        }
+    }
+
+    fn with_jitter(&self, delay: Duration) -> Duration {
+        if self.jitter == 0.0 {
+            return delay;
+        }
+
+        let random = RandomState::new().hash_one(delay) as f64 / u64::MAX as f64;
+
+        delay.mul_f64(1.0 - self.jitter * random)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.2,
+            retry_on: OllamaError::is_retryable,
+        }
+    }
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn should_back_off_exponentially_up_to_the_max() {
+        let policy =
+            RetryPolicy::new().backoff(Duration::from_millis(100), Duration::from_millis(350));
+
+        assert_eq!(policy.backoff_for(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff_for(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff_for(3), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn should_retry_until_the_budget_is_exhausted() {
+        let policy = RetryPolicy::new().max_attempts(3).jitter(0.0);
+        let mut attempts = 0;
+        let mut sleeps = vec![];
+
+        let result: Result<(), OllamaError> = policy.run_with_sleep(
+            || {
+                attempts += 1;
+                Err(OllamaError::Http {
+                    status: 503,
+                    body: String::new(),
+                })
+            },
+            |delay| sleeps.push(delay),
+        );
+
+        assert_eq!(attempts, 3);
+        assert_eq!(
+            sleeps,
+            vec![Duration::from_millis(500), Duration::from_millis(1000)]
+        );
+        assert!(matches!(
+            result,
+            Err(OllamaError::RetriesExhausted { attempts: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn should_not_retry_errors_that_will_not_go_away() {
+        let mut attempts = 0;
+
+        let result: Result<(), OllamaError> = RetryPolicy::new().run_with_sleep(
+            || {
+                attempts += 1;
+                Err(OllamaError::ModelNotFound("llama9".to_owned()))
+            },
+            |_| {},
+        );
+
+        assert_eq!(attempts, 1);
+        assert!(matches!(result, Err(OllamaError::ModelNotFound(_))));
+    }
+
+    #[test]
+    fn should_return_as_soon_as_an_attempt_succeeds() {
+        let mut attempts = 0;
+
+        let result = RetryPolicy::new().run_with_sleep(
+            || {
+                attempts += 1;
+
+                if attempts < 2 {
+                    Err(OllamaError::Http {
+                        status: 500,
+                        body: String::new(),
+                    })
+                } else {
+                    Ok(attempts)
+                }
+            },
+            |_| {},
+        );
+
+        assert_eq!(result.unwrap(), 2);
+    }
+
+    #[test]
+    fn should_only_shorten_delays_with_jitter() {
+        let policy = RetryPolicy::new().jitter(0.5);
+        let delay = Duration::from_secs(1);
+
+        for _ in 0..20 {
+            let jittered = policy.with_jitter(delay);
+
+            assert!(jittered <= delay && jittered >= delay / 2);
+        }
+    }
+}
@@ -0,0 +1,699 @@
+//! Describes Rust types as JSON schemas so that Ollama can be asked to reply in their shape.
+//!
+//! The schema is found by running the type's `Deserialize` implementation against a deserializer
+//! that records what it is asked for instead of reading any input. This works for plain structs,
+//! sequences, maps, options and enums made only of unit variants. A struct field is required
+//! unless the struct can be deserialized without it, so `Option` fields and fields with
+//! `#[serde(default)]` are left out of `required`.
+//!
+//! Anything else returns a `SchemaError` rather than a wrong schema. That includes types that
+//! have to look at the input to decide what they are, such as untagged enums, `#[serde(flatten)]`
+//! or `serde_json::Value`, and types that validate their contents, such as dates or `NonZeroU32`.
+
+use std::fmt::Display;
+
+use serde::{
+    de::{
+        self, value::BorrowedStrDeserializer, DeserializeOwned, DeserializeSeed, EnumAccess,
+        MapAccess, SeqAccess, VariantAccess, Visitor,
+    },
+    forward_to_deserialize_any, Deserializer,
+};
+use serde_json::{json, Map, Value};
+
+/// Creates the JSON schema for `T`.
+pub fn schema_for<T: DeserializeOwned>() -> Result<Value, SchemaError> {
+    let mut context = Context::default();
+
+    trace::<T>(&mut context)?;
+
+    // a trace only takes the first variant of each enum, so the others are traced on their own
+    // to make sure none of them carry data
+    for (path, variants) in std::mem::take(&mut context.enums) {
+        for variant in 1..variants {
+            context.variant = Some((path.clone(), variant));
+            trace::<T>(&mut context)?;
+        }
+    }
+
+    context.variant = None;
+
+    // every field is traced again without it, to find the ones the type can do without
+    for field in std::mem::take(&mut context.fields) {
+        context.omit = Some(field);
+
+        if trace::<T>(&mut context).is_ok() {
+            context.optional.extend(context.omit.take());
+        }
+    }
+
+    context.omit = None;
+
+    trace::<T>(&mut context)
+}
+
+fn trace<T: DeserializeOwned>(context: &mut Context) -> Result<Value, SchemaError> {
+    let mut traced = Traced::default();
+
+    context.path.clear();
+    T::deserialize(Tracer {
+        traced: &mut traced,
+        context,
+    })?;
+
+    Ok(traced.schema)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaError(String);
+
+impl Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "could not describe the type as a JSON schema: {}",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl de::Error for SchemaError {
+    fn custom<T: Display>(message: T) -> Self {
+        Self(message.to_string())
+    }
+}
+
+#[derive(Default)]
+struct Traced {
+    schema: Value,
+}
+
+/// The path to a value from the top of the type, made of field names, `[]` for the items of a
+/// sequence and `{}` for the values of a map.
+type Path = Vec<String>;
+
+/// What is known about the type across every time it is traced.
+#[derive(Default)]
+struct Context {
+    /// The structs currently being described, so that recursive types are caught instead of
+    /// looping.
+    structs: Vec<&'static str>,
+    path: Path,
+    /// Every struct field that has been traced.
+    fields: Vec<Path>,
+    /// The struct fields that can be left out.
+    optional: Vec<Path>,
+    /// The struct field to leave out of this trace.
+    omit: Option<Path>,
+    /// Every enum that has been traced along with how many variants it has.
+    enums: Vec<(Path, usize)>,
+    /// The enum variant to take in this trace instead of the first one.
+    variant: Option<(Path, usize)>,
+}
+
+impl Context {
+    fn trace_at<'de, T: DeserializeSeed<'de>>(
+        &mut self,
+        segment: &str,
+        traced: &mut Traced,
+        seed: T,
+    ) -> Result<T::Value, SchemaError> {
+        self.path.push(segment.to_owned());
+        let value = seed.deserialize(Tracer {
+            traced,
+            context: &mut *self,
+        });
+        self.path.pop();
+
+        value
+    }
+
+    fn field_path(&self, field: &str) -> Path {
+        let mut path = self.path.clone();
+        path.push(field.to_owned());
+
+        path
+    }
+}
+
+/// Records the schema of whatever is deserialized through it into `traced`.
+struct Tracer<'a> {
+    traced: &'a mut Traced,
+    context: &'a mut Context,
+}
+
+impl Tracer<'_> {
+    fn record<T>(self, schema: Value, value: Result<T, SchemaError>) -> Result<T, SchemaError> {
+        self.traced.schema = schema;
+
+        value
+    }
+}
+
+impl<'de> Deserializer<'de> for Tracer<'_> {
+    type Error = SchemaError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(SchemaError(
+            "a type that only knows its shape after seeing the data was found".to_owned(),
+        ))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.record(json!({ "type": "boolean" }), visitor.visit_bool(false))
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.record(json!({ "type": "integer" }), visitor.visit_i64(0))
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.record(
+            json!({ "type": "integer", "minimum": 0 }),
+            visitor.visit_u64(0),
+        )
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.record(json!({ "type": "number" }), visitor.visit_f64(0.0))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.record(
+            json!({ "type": "string", "minLength": 1, "maxLength": 1 }),
+            visitor.visit_char(' '),
+        )
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.record(json!({ "type": "string" }), visitor.visit_str(""))
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.record(
+            json!({ "type": "array", "items": { "type": "integer", "minimum": 0, "maximum": 255 } }),
+            visitor.visit_bytes(&[]),
+        )
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.record(json!({ "type": "null" }), visitor.visit_unit())
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let mut items = vec![];
+        let value = visitor.visit_seq(SeqTracer {
+            remaining: 1,
+            items: &mut items,
+            context: &mut *self.context,
+        })?;
+        let items = items.pop().unwrap_or_else(|| json!({}));
+
+        self.record(json!({ "type": "array", "items": items }), Ok(value))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let mut items = vec![];
+        let value = visitor.visit_seq(SeqTracer {
+            remaining: len,
+            items: &mut items,
+            context: &mut *self.context,
+        })?;
+
+        self.record(
+            json!({ "type": "array", "prefixItems": items, "minItems": len, "maxItems": len }),
+            Ok(value),
+        )
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let mut values = Traced::default();
+        let value = visitor.visit_map(MapTracer {
+            done: false,
+            values: &mut values,
+            context: &mut *self.context,
+        })?;
+
+        self.record(
+            json!({ "type": "object", "additionalProperties": values.schema }),
+            Ok(value),
+        )
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if self.context.structs.contains(&name) {
+            return Err(SchemaError(format!(
+                "{name} contains itself, recursive types are not supported"
+            )));
+        }
+
+        let mut properties = Map::new();
+        let mut required = vec![];
+
+        self.context.structs.push(name);
+        let value = visitor.visit_map(StructTracer {
+            fields: fields.iter(),
+            current: None,
+            properties: &mut properties,
+            required: &mut required,
+            context: &mut *self.context,
+        });
+        self.context.structs.pop();
+        let value = value?;
+
+        self.record(
+            json!({ "type": "object", "properties": properties, "required": required }),
+            Ok(value),
+        )
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if variants.is_empty() {
+            return Err(SchemaError(format!("{name} has no variants")));
+        }
+
+        let path = self.context.path.clone();
+        let index = match &self.context.variant {
+            Some((variant_path, index)) if *variant_path == path => *index,
+            _ => 0,
+        };
+
+        if !self.context.enums.iter().any(|(traced, _)| *traced == path) {
+            self.context.enums.push((path, variants.len()));
+        }
+
+        let value = visitor.visit_enum(EnumTracer {
+            name,
+            variant: variants[index],
+        });
+
+        self.record(json!({ "type": "string", "enum": variants }), value)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! { identifier }
+}
+
+/// Hands out `remaining` elements, recording the schema of each one into `items`.
+struct SeqTracer<'a> {
+    remaining: usize,
+    items: &'a mut Vec<Value>,
+    context: &'a mut Context,
+}
+
+impl<'de> SeqAccess<'de> for SeqTracer<'_> {
+    type Error = SchemaError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        self.remaining -= 1;
+
+        let mut traced = Traced::default();
+        let value = self.context.trace_at("[]", &mut traced, seed)?;
+
+        self.items.push(traced.schema);
+
+        Ok(Some(value))
+    }
+}
+
+/// Hands out a single entry so the schema of the map's values can be recorded.
+struct MapTracer<'a> {
+    done: bool,
+    values: &'a mut Traced,
+    context: &'a mut Context,
+}
+
+impl<'de> MapAccess<'de> for MapTracer<'_> {
+    type Error = SchemaError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        if self.done {
+            return Ok(None);
+        }
+
+        seed.deserialize(Tracer {
+            traced: &mut Traced::default(),
+            context: &mut *self.context,
+        })
+        .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.done = true;
+
+        self.context.trace_at("{}", self.values, seed)
+    }
+}
+
+/// Hands out every field of a struct, recording each field's schema and whether it is required.
+struct StructTracer<'a> {
+    fields: std::slice::Iter<'static, &'static str>,
+    current: Option<&'static str>,
+    properties: &'a mut Map<String, Value>,
+    required: &'a mut Vec<String>,
+    context: &'a mut Context,
+}
+
+impl<'de> MapAccess<'de> for StructTracer<'_> {
+    type Error = SchemaError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let omit = self.context.omit.as_ref();
+        let Some(field) = self
+            .fields
+            .find(|field| omit != Some(&self.context.field_path(field)))
+        else {
+            return Ok(None);
+        };
+
+        self.current = Some(field);
+
+        seed.deserialize(BorrowedStrDeserializer::new(field))
+            .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let field = self
+            .current
+            .take()
+            .ok_or_else(|| SchemaError("a value was asked for before its field".to_owned()))?;
+        let path = self.context.field_path(field);
+        let mut traced = Traced::default();
+        let value = self.context.trace_at(field, &mut traced, seed)?;
+
+        if !self.context.optional.contains(&path) {
+            self.required.push(field.to_owned());
+        }
+
+        if !self.context.fields.contains(&path) {
+            self.context.fields.push(path);
+        }
+
+        self.properties.insert(field.to_owned(), traced.schema);
+
+        Ok(value)
+    }
+}
+
+/// Picks the first variant of an enum, which must be a unit variant.
+struct EnumTracer {
+    name: &'static str,
+    variant: &'static str,
+}
+
+impl<'de> EnumAccess<'de> for EnumTracer {
+    type Error = SchemaError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let variant = seed.deserialize(BorrowedStrDeserializer::new(self.variant))?;
+
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for EnumTracer {
+    type Error = SchemaError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        _seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        Err(self.unsupported())
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        Err(self.unsupported())
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        Err(self.unsupported())
+    }
+}
+
+impl EnumTracer {
+    fn unsupported(&self) -> SchemaError {
+        SchemaError(format!(
+            "{}::{} carries data, only enums made of unit variants are supported",
+            self.name, self.variant
+        ))
+    }
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use serde::Deserialize;
+    #[allow(unused_imports)]
+    use std::collections::HashMap;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Priority {
+        Low,
+        High,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Subtask {
+        name: String,
+        minutes: u32,
+        priority: Priority,
+        notes: Option<String>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Plan {
+        done: bool,
+        subtasks: Vec<Subtask>,
+        estimates: HashMap<String, f64>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    enum Mixed {
+        Unit,
+        Data(u32),
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct WithMixed {
+        name: String,
+        mixed: Option<Mixed>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Node {
+        children: Vec<Node>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Tagged {
+        #[serde(default)]
+        tags: Vec<String>,
+        name: String,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Flattened {
+        #[serde(flatten)]
+        tagged: Tagged,
+    }
+
+    #[test]
+    fn should_describe_nested_structs() -> Result<(), SchemaError> {
+        let schema = schema_for::<Plan>()?;
+
+        assert_eq!(
+            schema,
+            json!({
+                "type": "object",
+                "properties": {
+                    "done": { "type": "boolean" },
+                    "subtasks": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "name": { "type": "string" },
+                                "minutes": { "type": "integer", "minimum": 0 },
+                                "priority": { "type": "string", "enum": ["low", "high"] },
+                                "notes": { "type": "string" }
+                            },
+                            "required": ["name", "minutes", "priority"]
+                        }
+                    },
+                    "estimates": {
+                        "type": "object",
+                        "additionalProperties": { "type": "number" }
+                    }
+                },
+                "required": ["done", "subtasks", "estimates"]
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_describe_a_top_level_array() -> Result<(), SchemaError> {
+        assert_eq!(
+            schema_for::<Vec<String>>()?,
+            json!({ "type": "array", "items": { "type": "string" } })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_refuse_types_it_cannot_describe() {
+        assert!(schema_for::<Node>().is_err());
+        assert!(schema_for::<Value>().is_err());
+        assert!(schema_for::<Flattened>().is_err());
+        assert!(schema_for::<std::num::NonZeroU32>().is_err());
+        assert!(schema_for::<Mixed>().is_err());
+        assert!(schema_for::<Vec<WithMixed>>().is_err());
+    }
+
+    #[test]
+    fn should_not_require_defaulted_fields() -> Result<(), SchemaError> {
+        let schema = schema_for::<Tagged>()?;
+
+        assert_eq!(schema["required"], json!(["name"]));
+
+        let plans = schema_for::<Vec<Plan>>()?;
+
+        assert_eq!(
+            plans["items"]["properties"]["subtasks"]["items"]["required"],
+            json!(["name", "minutes", "priority"])
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+//! `#[derive(OllamaTool)]`, which turns a struct into a tool the model can call. Use it through
+//! `bb_ollama::models::tool::OllamaTool`, this crate only holds the macro.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput, Error, Expr, ExprLit,
+    Fields, Lit, LitStr, Meta,
+};
+
+/// Describes a tool from a struct. The struct's doc comment becomes the tool description and
+/// each field's doc comment the description of that argument. `#[tool(name = "...")]` on the
+/// struct sets the tool name, which otherwise is the struct name in snake_case. Fields accept
+/// `#[tool(rename = "...", minimum = .., maximum = .., format = "...")]`.
+#[proc_macro_derive(OllamaTool, attributes(tool))]
+pub fn derive_ollama_tool(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let ident = &input.ident;
+    let mut name = to_snake_case(&ident.to_string());
+
+    for attribute in tool_attributes(&input.attrs) {
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("expected `name`"))
+            }
+        })?;
+    }
+
+    let Some(description) = doc_comment(&input.attrs) else {
+        return Err(Error::new(
+            ident.span(),
+            "add a doc comment to describe the tool to the model",
+        ));
+    };
+
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new(
+            ident.span(),
+            "OllamaTool can only be derived for structs",
+        ));
+    };
+
+    let fields = match &data.fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(ToolField::parse)
+            .collect::<Result<Vec<_>, Error>>()?,
+        Fields::Unit => vec![],
+        Fields::Unnamed(fields) => {
+            return Err(Error::new(
+                fields.span(),
+                "tool arguments need names, use a struct with named fields",
+            ))
+        }
+    };
+
+    let properties = fields.iter().map(ToolField::property);
+    let construct = if matches!(data.fields, Fields::Unit) {
+        quote!(Self)
+    } else {
+        let values = fields.iter().map(ToolField::value);
+        quote!(Self { #(#values,)* })
+    };
+
+    Ok(quote! {
+        impl ::bb_ollama::models::tool::OllamaTool for #ident {
+            fn name() -> &'static str {
+                #name
+            }
+
+            fn tool() -> ::std::result::Result<
+                ::bb_ollama::models::tool::Tool,
+                ::bb_ollama::models::tool::ToolError,
+            > {
+                #[allow(unused_mut)]
+                let mut builder = ::bb_ollama::models::tool::Tool::new()
+                    .function_name(#name)
+                    .function_description(#description);
+
+                #(#properties)*
+
+                builder.build()
+            }
+
+            fn from_arguments(
+                arguments: &::bb_ollama::models::tool_call::Arguments,
+            ) -> ::std::result::Result<Self, ::bb_ollama::serde_json::Error> {
+                let _ = arguments;
+
+                ::std::result::Result::Ok(#construct)
+            }
+        }
+    })
+}
+
+struct ToolField {
+    ident: syn::Ident,
+    ty: syn::Type,
+    name: String,
+    description: String,
+    modifiers: Vec<TokenStream2>,
+}
+
+impl ToolField {
+    fn parse(field: &syn::Field) -> Result<Self, Error> {
+        let ident = field.ident.clone().expect("named fields have identifiers");
+        let mut name = ident.to_string();
+        let mut modifiers = vec![];
+
+        for attribute in tool_attributes(&field.attrs) {
+            attribute.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                } else if meta.path.is_ident("minimum") {
+                    let minimum = meta.value()?.parse::<Expr>()?;
+                    modifiers.push(quote!(.minimum(#minimum)));
+                } else if meta.path.is_ident("maximum") {
+                    let maximum = meta.value()?.parse::<Expr>()?;
+                    modifiers.push(quote!(.maximum(#maximum)));
+                } else if meta.path.is_ident("format") {
+                    let format = meta.value()?.parse::<LitStr>()?;
+                    modifiers.push(quote!(.format(#format)));
+                } else {
+                    return Err(meta.error("expected `rename`, `minimum`, `maximum` or `format`"));
+                }
+
+                Ok(())
+            })?;
+        }
+
+        Ok(Self {
+            ident,
+            ty: field.ty.clone(),
+            name,
+            description: doc_comment(&field.attrs).unwrap_or_default(),
+            modifiers,
+        })
+    }
+
+    fn property(&self) -> TokenStream2 {
+        let Self {
+            ty,
+            name,
+            description,
+            modifiers,
+            ..
+        } = self;
+
+        quote! {
+            builder = builder.add_function_property(
+                #name,
+                <#ty as ::bb_ollama::models::tool::ToolArgument>::property(#description) #(#modifiers)*,
+            );
+            if <#ty as ::bb_ollama::models::tool::ToolArgument>::REQUIRED {
+                builder = builder.add_required_property(#name);
+            }
+        }
+    }
+
+    fn value(&self) -> TokenStream2 {
+        let Self { ident, name, .. } = self;
+
+        quote!(#ident: ::bb_ollama::models::tool::argument(arguments, #name)?)
+    }
+}
+
+fn tool_attributes(attributes: &[Attribute]) -> impl Iterator<Item = &Attribute> {
+    attributes
+        .iter()
+        .filter(|attribute| attribute.path().is_ident("tool"))
+}
+
+/// Joins the lines of a doc comment into a single line of text.
+fn doc_comment(attributes: &[Attribute]) -> Option<String> {
+    let lines = attributes
+        .iter()
+        .filter_map(|attribute| match &attribute.meta {
+            Meta::NameValue(meta) if meta.path.is_ident("doc") => match &meta.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(line),
+                    ..
+                }) => Some(line.value().trim().to_owned()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+
+    (!lines.is_empty()).then(|| lines.join(" "))
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut snake_case = String::new();
+
+    for (index, character) in name.chars().enumerate() {
+        if character.is_uppercase() && index > 0 {
+            snake_case.push('_');
+        }
+
+        snake_case.extend(character.to_lowercase());
+    }
+
+    snake_case
+}
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS tasks (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    completed BOOLEAN NOT NULL DEFAULT FALSE
+);
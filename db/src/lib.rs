@@ -1,15 +1,59 @@
-use std::{env, fmt::Display};
+pub mod migrations;
+pub mod tags;
+pub mod task_query;
 
-use eyre::{Context, Result};
-use postgres::Row;
+use std::{env, fmt::Display, ops::RangeInclusive};
+
+use chrono::{DateTime, Local, Utc};
+use eyre::{bail, Context, Result};
+use postgres::{types::ToSql, Row};
 pub use postgres::{Client, NoTls};
+use serde::Serialize;
+
+pub use migrations::{migrate, schema_status};
+pub use tags::{list_tags, tag_task, untag_task, DbTag};
+use task_query::TASK_TAGS;
+pub use task_query::{TaskOrder, TaskQuery};
+
+/// What `connect` does about migrations that haven't been applied to the database yet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchemaCheck {
+    /// Connect without looking at the schema.
+    #[default]
+    Skip,
+    /// Refuse to connect while any migration is pending.
+    Require,
+    /// Apply pending migrations before returning the client.
+    Migrate,
+}
 
-pub fn connect() -> Result<Client> {
+pub fn connect(schema_check: SchemaCheck) -> Result<Client> {
     dotenvy::dotenv().ok();
 
     let database_url =
         env::var("DATABASE_URL").context("Getting database url from environment variable")?;
-    Client::connect(&database_url, NoTls).context("connecting to postgres database")
+    let mut db =
+        Client::connect(&database_url, NoTls).context("connecting to postgres database")?;
+
+    match schema_check {
+        SchemaCheck::Skip => {}
+        SchemaCheck::Require => {
+            let status = schema_status(&mut db)?;
+
+            if !status.is_up_to_date() {
+                bail!(
+                    "The database schema is at version {} but this app needs version {}, run the migrations first",
+                    status.current_version,
+                    status.pending.last().map_or(status.current_version, |migration| migration.version)
+                );
+            }
+        }
+        SchemaCheck::Migrate => {
+            migrate(&mut db).context("migrating the database")?;
+        }
+    }
+
+    Ok(db)
 }
 
 pub fn run_query(db: &mut Client, sql: &str) -> Result<String> {
@@ -20,49 +64,73 @@ pub fn run_query(db: &mut Client, sql: &str) -> Result<String> {
     ))
 }
 
-pub fn insert(db: &mut Client, name: &str) -> Result<DbTask> {
+/// How important a task is, from 1 for the least important to 5 for the most urgent.
+pub const PRIORITIES: RangeInclusive<i16> = 1..=5;
+pub const DEFAULT_PRIORITY: i16 = 3;
+
+/// Inserts a task, with the default priority when `priority` is `None`.
+pub fn insert(
+    db: &mut Client,
+    name: &str,
+    due_at: Option<DateTime<Utc>>,
+    priority: Option<i16>,
+) -> Result<DbTask> {
+    let priority = check_priority(priority.unwrap_or(DEFAULT_PRIORITY))?;
     let result = db
-        .query_one("INSERT INTO tasks (name) values ($1) RETURNING *", &[&name])
+        .query_one(
+            &format!(
+                "INSERT INTO tasks (name, due_at, priority) values ($1, $2, $3) RETURNING *, {TASK_TAGS} AS tags"
+            ),
+            &[&name, &due_at, &priority],
+        )
         .context("Inserting into database")?;
 
     Ok(result.into())
 }
 
 pub fn get_all_tasks(db: &mut Client) -> Result<Vec<DbTask>> {
-    let results = db
-        .query("SELECT * FROM tasks;", &[])
-        .context("running query")?;
-    Ok(results
-        .into_iter()
-        .map(|row| DbTask {
-            id: row.get::<_, i32>("id"),
-            name: row.get::<_, String>("name"),
-            completed: row.get::<_, bool>("completed"),
-        })
-        .collect())
+    find_tasks(db, &TaskQuery::new())
 }
 
-pub fn get_task_by_id(db: &mut Client, id: i32) -> Result<Option<DbTask>> {
-    let Some(row) = db
-        .query_opt("SELECT * FROM tasks WHERE id = $1;", &[&id])
-        .context("running query")?
-    else {
-        return Ok(None);
-    };
+/// The tasks matching the query, in the order it asks for.
+pub fn find_tasks(db: &mut Client, query: &TaskQuery) -> Result<Vec<DbTask>> {
+    let (sql, params) = query.to_sql();
+    let params = params
+        .iter()
+        .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+        .collect::<Vec<_>>();
+    let rows = db.query(&sql, &params).context("finding tasks")?;
 
-    let task = DbTask {
-        id: row.get::<_, i32>("id"),
-        name: row.get::<_, String>("name"),
-        completed: row.get::<_, bool>("completed"),
-    };
-    Ok(Some(task))
+    Ok(rows.into_iter().map(DbTask::from).collect())
+}
+
+/// Tasks that aren't completed and are past their due date.
+pub fn overdue_tasks(db: &mut Client) -> Result<Vec<DbTask>> {
+    find_tasks(db, &TaskQuery::overdue(Utc::now()))
+}
+
+/// Tasks that aren't completed and are due today in the local time zone.
+pub fn tasks_due_today(db: &mut Client) -> Result<Vec<DbTask>> {
+    find_tasks(db, &TaskQuery::due_today(Local::now()))
+}
+
+/// Tasks that aren't completed and are due in the next `days` days.
+pub fn tasks_due_within_days(db: &mut Client, days: u32) -> Result<Vec<DbTask>> {
+    find_tasks(db, &TaskQuery::due_within_days(Utc::now(), days))
+}
+
+pub fn get_task_by_id(db: &mut Client, id: i32) -> Result<Option<DbTask>> {
+    Ok(find_tasks(db, &TaskQuery::new().ids([id]))?.pop())
 }
 
+/// Changes the fields that are `Some`. `due_at` is `Some(None)` to clear the due date.
 pub fn update(
     db: &mut Client,
     id: i32,
     name: Option<&str>,
     completed: Option<bool>,
+    due_at: Option<Option<DateTime<Utc>>>,
+    priority: Option<i16>,
 ) -> Result<Option<DbTask>> {
     let Some(mut task) = get_task_by_id(db, id)? else {
         return Ok(None);
@@ -77,16 +145,36 @@ pub fn update(
         task.completed = completed;
     }
 
+    if let Some(due_at) = due_at {
+        task.due_at = due_at;
+    }
+
+    if let Some(priority) = priority {
+        task.priority = check_priority(priority)?;
+    }
+
     let row = db
         .query_one(
-            "UPDATE tasks SET (name, completed) = ($1, $2) WHERE id = $3 RETURNING *;",
-            &[&task.name, &task.completed, &task.id],
+            &format!("UPDATE tasks SET (name, completed, due_at, priority) = ($1, $2, $3, $4) WHERE id = $5 RETURNING *, {TASK_TAGS} AS tags;"),
+            &[&task.name, &task.completed, &task.due_at, &task.priority, &task.id],
         )
         .context("running update")?;
 
     Ok(Some(row.into()))
 }
 
+fn check_priority(priority: i16) -> Result<i16> {
+    if !PRIORITIES.contains(&priority) {
+        bail!(
+            "The priority {priority} is not valid, it has to be from {} to {}",
+            PRIORITIES.start(),
+            PRIORITIES.end()
+        );
+    }
+
+    Ok(priority)
+}
+
 pub fn delete(db: &mut Client, id: i32) -> Result<u64> {
     db.execute("DELETE FROM tasks WHERE id = $1", &[&id])
         .context("deleting task from database")
@@ -97,11 +185,15 @@ pub fn erase(db: &mut Client) -> Result<u64> {
         .context("Erasing the database")
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct DbTask {
     pub id: i32,
     pub name: String,
     pub completed: bool,
+    pub due_at: Option<DateTime<Utc>>,
+    pub priority: i16,
+    pub created_at: DateTime<Utc>,
+    pub tags: Vec<String>,
 }
 
 impl From<Row> for DbTask {
@@ -110,6 +202,10 @@ impl From<Row> for DbTask {
             id: row.get::<_, i32>("id"),
             name: row.get::<_, String>("name"),
             completed: row.get::<_, bool>("completed"),
+            due_at: row.get::<_, Option<DateTime<Utc>>>("due_at"),
+            priority: row.get::<_, i16>("priority"),
+            created_at: row.get::<_, DateTime<Utc>>("created_at"),
+            tags: row.get::<_, Vec<String>>("tags"),
         }
     }
 }
@@ -118,8 +214,31 @@ impl Display for DbTask {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "id: {}, name: {}, completed: {}",
-            self.id, self.name, self.completed
-        )
+            "id: {}, name: {}, completed: {}, priority: {}",
+            self.id, self.name, self.completed, self.priority
+        )?;
+
+        if let Some(due_at) = self.due_at {
+            write!(f, ", due at: {}", due_at.to_rfc3339())?;
+        }
+
+        if !self.tags.is_empty() {
+            write!(f, ", tags: {}", self.tags.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn should_only_accept_priorities_from_one_to_five() {
+        assert_eq!(check_priority(1).ok(), Some(1));
+        assert_eq!(check_priority(5).ok(), Some(5));
+        assert!(check_priority(0).is_err());
+        assert!(check_priority(6).is_err());
     }
 }
@@ -0,0 +1,283 @@
+use std::fmt::Write;
+
+use eyre::{bail, Context, Result};
+use postgres::Client;
+use sha2::{Digest, Sha256};
+
+/// A versioned change to the schema. The SQL is compiled into the binary from `db/migrations`, so
+/// a database can be set up without the repo checked out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    /// The SHA-256 of the SQL as hex, recorded when the migration is applied so that editing an
+    /// applied migration is caught instead of silently diverging from the database.
+    pub fn checksum(&self) -> String {
+        Sha256::digest(self.sql.as_bytes())
+            .iter()
+            .fold(String::new(), |mut checksum, byte| {
+                let _ = write!(checksum, "{byte:02x}");
+                checksum
+            })
+    }
+}
+
+/// Every migration in the order it's applied. Add new migrations to the end with the next
+/// version, and never edit one that has been applied.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_tasks",
+        sql: include_str!("../migrations/0001_create_tasks.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "add_task_due_dates",
+        sql: include_str!("../migrations/0002_add_task_due_dates.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "add_task_priority_and_created_at",
+        sql: include_str!("../migrations/0003_add_task_priority_and_created_at.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "add_tags",
+        sql: include_str!("../migrations/0004_add_tags.sql"),
+    },
+];
+
+/// A migration the database has recorded as applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedMigration {
+    pub version: i32,
+    pub checksum: String,
+}
+
+/// Where the database schema is compared to the migrations in this build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaStatus {
+    pub current_version: i32,
+    pub pending: Vec<Migration>,
+}
+
+impl SchemaStatus {
+    pub fn is_up_to_date(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+const CREATE_SCHEMA_MIGRATIONS: &str = "CREATE TABLE IF NOT EXISTS schema_migrations (
+    version INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    checksum TEXT NOT NULL,
+    applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);";
+
+/// The key of the advisory lock held while migrating, so that two copies of the app starting at
+/// once don't both apply the same migration.
+const MIGRATION_LOCK: i64 = 0x7461_736b_6d69_6772;
+
+/// Applies every pending migration, each in its own transaction, and returns the versions that
+/// were applied. Fails without changing anything if an applied migration has been edited. Waits
+/// for any other connection that is migrating the same database to finish first.
+pub fn migrate(db: &mut Client) -> Result<Vec<i32>> {
+    db.execute("SELECT pg_advisory_lock($1);", &[&MIGRATION_LOCK])
+        .context("waiting for other migrations to finish")?;
+
+    let applied = apply_pending_migrations(db);
+
+    db.execute("SELECT pg_advisory_unlock($1);", &[&MIGRATION_LOCK])
+        .context("releasing the migration lock")?;
+
+    applied
+}
+
+fn apply_pending_migrations(db: &mut Client) -> Result<Vec<i32>> {
+    db.batch_execute(CREATE_SCHEMA_MIGRATIONS)
+        .context("creating the schema_migrations table")?;
+
+    let pending = pending_migrations(MIGRATIONS, &applied_migrations(db)?)?;
+    let mut applied = vec![];
+
+    for migration in pending {
+        let mut transaction = db.transaction().context("starting a migration")?;
+
+        transaction
+            .batch_execute(migration.sql)
+            .with_context(|| format!("applying migration {}", migration.version))?;
+        transaction
+            .execute(
+                "INSERT INTO schema_migrations (version, name, checksum) VALUES ($1, $2, $3);",
+                &[&migration.version, &migration.name, &migration.checksum()],
+            )
+            .context("recording the migration")?;
+        transaction
+            .commit()
+            .with_context(|| format!("committing migration {}", migration.version))?;
+
+        applied.push(migration.version);
+    }
+
+    Ok(applied)
+}
+
+/// Compares the database to the migrations in this build without changing anything.
+pub fn schema_status(db: &mut Client) -> Result<SchemaStatus> {
+    let has_table = db
+        .query_one("SELECT to_regclass('schema_migrations') IS NOT NULL;", &[])
+        .context("looking for the schema_migrations table")?
+        .get::<_, bool>(0);
+    let applied = if has_table {
+        applied_migrations(db)?
+    } else {
+        vec![]
+    };
+
+    Ok(SchemaStatus {
+        current_version: applied
+            .iter()
+            .map(|migration| migration.version)
+            .max()
+            .unwrap_or(0),
+        pending: pending_migrations(MIGRATIONS, &applied)?,
+    })
+}
+
+/// The migrations that haven't been applied yet, in order. Errors if an applied migration's
+/// checksum has changed or the database has a migration this build doesn't know about.
+pub fn pending_migrations(
+    migrations: &[Migration],
+    applied: &[AppliedMigration],
+) -> Result<Vec<Migration>> {
+    for applied in applied {
+        let Some(migration) = migrations
+            .iter()
+            .find(|migration| migration.version == applied.version)
+        else {
+            bail!(
+                "The database has migration {} which this build doesn't know about, it may be newer than this app",
+                applied.version
+            );
+        };
+
+        if migration.checksum() != applied.checksum {
+            bail!(
+                "Migration {} ({}) has changed since it was applied to the database",
+                migration.version,
+                migration.name
+            );
+        }
+    }
+
+    Ok(migrations
+        .iter()
+        .filter(|migration| {
+            !applied
+                .iter()
+                .any(|applied| applied.version == migration.version)
+        })
+        .copied()
+        .collect())
+}
+
+fn applied_migrations(db: &mut Client) -> Result<Vec<AppliedMigration>> {
+    let rows = db
+        .query(
+            "SELECT version, checksum FROM schema_migrations ORDER BY version;",
+            &[],
+        )
+        .context("reading applied migrations")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| AppliedMigration {
+            version: row.get::<_, i32>("version"),
+            checksum: row.get::<_, String>("checksum"),
+        })
+        .collect())
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(dead_code)]
+    fn applied(migration: &Migration) -> AppliedMigration {
+        AppliedMigration {
+            version: migration.version,
+            checksum: migration.checksum(),
+        }
+    }
+
+    #[test]
+    fn should_number_migrations_in_order() {
+        let versions = MIGRATIONS
+            .iter()
+            .map(|migration| migration.version)
+            .collect::<Vec<_>>();
+
+        assert_eq!(versions, (1..=MIGRATIONS.len() as i32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn should_only_return_migrations_that_have_not_been_applied() -> Result<()> {
+        let migrations = [
+            Migration {
+                version: 1,
+                name: "one",
+                sql: "SELECT 1;",
+            },
+            Migration {
+                version: 2,
+                name: "two",
+                sql: "SELECT 2;",
+            },
+        ];
+
+        let pending = pending_migrations(&migrations, &[applied(&migrations[0])])?;
+
+        assert_eq!(pending, vec![migrations[1]]);
+        assert_eq!(pending_migrations(&migrations, &[])?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_refuse_edited_or_unknown_migrations() {
+        let migration = Migration {
+            version: 1,
+            name: "one",
+            sql: "SELECT 1;",
+        };
+        let edited = AppliedMigration {
+            version: 1,
+            checksum: "not the checksum".to_owned(),
+        };
+        let unknown = AppliedMigration {
+            version: 2,
+            checksum: migration.checksum(),
+        };
+
+        assert!(pending_migrations(&[migration], &[edited]).is_err());
+        assert!(pending_migrations(&[migration], &[unknown]).is_err());
+    }
+
+    #[test]
+    fn should_checksum_the_sql_with_sha256() {
+        let migration = Migration {
+            version: 1,
+            name: "empty",
+            sql: "",
+        };
+
+        assert_eq!(
+            migration.checksum(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use eyre::{Context, Result};
+use postgres::Client;
+use serde::Serialize;
+
+/// A tag along with how many tasks have it.
+#[derive(Debug, Serialize)]
+pub struct DbTag {
+    pub name: String,
+    pub task_count: i64,
+}
+
+/// Tags are stored trimmed and in lowercase, so that "Errands" and "errands " are the same tag.
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Normalizes the tags, dropping empty and repeated ones.
+pub(crate) fn normalize_tags(tags: impl IntoIterator<Item = impl AsRef<str>>) -> Vec<String> {
+    let mut normalized: Vec<String> = vec![];
+
+    for tag in tags {
+        let tag = normalize_tag(tag.as_ref());
+
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+
+    normalized
+}
+
+/// Adds the tags to a task, creating any tag that doesn't exist yet. Returns false without
+/// changing anything when there is no task with the id.
+pub fn tag_task(
+    db: &mut Client,
+    task_id: i32,
+    tags: impl IntoIterator<Item = impl AsRef<str>>,
+) -> Result<bool> {
+    let mut transaction = db.transaction().context("starting to tag a task")?;
+
+    if transaction
+        .query_opt(
+            "SELECT id FROM tasks WHERE id = $1 FOR UPDATE;",
+            &[&task_id],
+        )
+        .context("finding the task to tag")?
+        .is_none()
+    {
+        return Ok(false);
+    }
+
+    for tag in normalize_tags(tags) {
+        let tag_id = transaction
+            .query_one(
+                "INSERT INTO tags (name) VALUES ($1) ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name RETURNING id;",
+                &[&tag],
+            )
+            .context("creating the tag")?
+            .get::<_, i32>("id");
+
+        transaction
+            .execute(
+                "INSERT INTO task_tags (task_id, tag_id) VALUES ($1, $2) ON CONFLICT DO NOTHING;",
+                &[&task_id, &tag_id],
+            )
+            .context("tagging the task")?;
+    }
+
+    transaction.commit().context("committing the tags")?;
+
+    Ok(true)
+}
+
+/// Removes the tags from a task and returns how many were removed. The tags themselves are kept.
+pub fn untag_task(
+    db: &mut Client,
+    task_id: i32,
+    tags: impl IntoIterator<Item = impl AsRef<str>>,
+) -> Result<u64> {
+    db.execute(
+        "DELETE FROM task_tags USING tags WHERE task_tags.tag_id = tags.id AND task_tags.task_id = $1 AND tags.name = ANY($2);",
+        &[&task_id, &normalize_tags(tags)],
+    )
+    .context("untagging the task")
+}
+
+/// Every tag in alphabetical order with the number of tasks that have it.
+pub fn list_tags(db: &mut Client) -> Result<Vec<DbTag>> {
+    let rows = db
+        .query(
+            "SELECT tags.name, COUNT(task_tags.task_id) AS task_count FROM tags LEFT JOIN task_tags ON task_tags.tag_id = tags.id GROUP BY tags.id ORDER BY tags.name;",
+            &[],
+        )
+        .context("listing tags")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| DbTag {
+            name: row.get::<_, String>("name"),
+            task_count: row.get::<_, i64>("task_count"),
+        })
+        .collect())
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn should_treat_tags_that_differ_in_case_or_spacing_as_the_same() {
+        assert_eq!(
+            normalize_tags(["Errands", " errands ", "", "Work"]),
+            vec!["errands".to_owned(), "work".to_owned()]
+        );
+    }
+}
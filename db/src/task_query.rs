@@ -0,0 +1,350 @@
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, TimeZone, Utc};
+use postgres::types::ToSql;
+
+use crate::tags::normalize_tags;
+
+/// The names of a task's tags in alphabetical order, for selecting alongside `tasks.*`.
+pub(crate) const TASK_TAGS: &str = "ARRAY(SELECT tags.name FROM task_tags JOIN tags ON tags.id = task_tags.tag_id WHERE task_tags.task_id = tasks.id ORDER BY tags.name)";
+
+/// The parameters of a query, boxed so that values of different types can be passed together.
+pub type QueryParams = Vec<Box<dyn ToSql + Sync>>;
+
+/// Which tasks `find_tasks` returns and in what order. Every filter that is left unset matches
+/// all tasks, so `TaskQuery::new()` returns every task ordered by id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaskQuery {
+    pub completed: Option<bool>,
+    pub name_contains: Option<String>,
+    pub ids: Option<Vec<i32>>,
+    /// Only tasks due at or after this time.
+    pub due_after: Option<DateTime<Utc>>,
+    /// Only tasks due before this time.
+    pub due_before: Option<DateTime<Utc>>,
+    /// Only tasks with at least one of these tags.
+    pub any_tags: Option<Vec<String>>,
+    /// Only tasks with every one of these tags.
+    pub all_tags: Option<Vec<String>>,
+    pub order_by: TaskOrder,
+    pub descending: bool,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TaskOrder {
+    #[default]
+    Id,
+    Name,
+    Completed,
+    DueAt,
+    Priority,
+    CreatedAt,
+}
+
+impl TaskOrder {
+    fn column(&self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::Name => "name",
+            Self::Completed => "completed",
+            Self::DueAt => "due_at",
+            Self::Priority => "priority",
+            Self::CreatedAt => "created_at",
+        }
+    }
+
+    fn is_nullable(&self) -> bool {
+        matches!(self, Self::DueAt)
+    }
+}
+
+impl TaskQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tasks that aren't completed and were due before `now`, the most overdue first.
+    pub fn overdue(now: DateTime<Utc>) -> Self {
+        Self::new()
+            .completed(false)
+            .due_before(now)
+            .order_by(TaskOrder::DueAt)
+    }
+
+    /// Tasks that aren't completed and are due on the same day as `now` in its time zone,
+    /// including the ones due earlier today.
+    pub fn due_today<Tz: TimeZone>(now: DateTime<Tz>) -> Self {
+        let today = now.date_naive();
+        let start_of_day = |date: NaiveDate| {
+            now.timezone()
+                .from_local_datetime(&date.and_time(NaiveTime::MIN))
+                .earliest()
+                .map(|start| start.with_timezone(&Utc))
+        };
+        let mut query = Self::new().completed(false).order_by(TaskOrder::DueAt);
+
+        query.due_after = start_of_day(today);
+        query.due_before = today.checked_add_days(Days::new(1)).and_then(start_of_day);
+
+        query
+    }
+
+    /// Tasks that aren't completed and are due between `now` and `days` days from now.
+    pub fn due_within_days(now: DateTime<Utc>, days: u32) -> Self {
+        let mut query = Self::new()
+            .completed(false)
+            .due_after(now)
+            .order_by(TaskOrder::DueAt);
+
+        query.due_before = now.checked_add_days(Days::new(days.into()));
+
+        query
+    }
+
+    pub fn completed(mut self, completed: bool) -> Self {
+        self.completed = Some(completed);
+
+        self
+    }
+
+    /// Only tasks whose name contains `name`, ignoring case.
+    pub fn name_contains(mut self, name: impl Into<String>) -> Self {
+        self.name_contains = Some(name.into());
+
+        self
+    }
+
+    /// Only the tasks with one of these ids.
+    pub fn ids(mut self, ids: impl IntoIterator<Item = i32>) -> Self {
+        self.ids = Some(ids.into_iter().collect());
+
+        self
+    }
+
+    pub fn due_after(mut self, due_after: DateTime<Utc>) -> Self {
+        self.due_after = Some(due_after);
+
+        self
+    }
+
+    pub fn due_before(mut self, due_before: DateTime<Utc>) -> Self {
+        self.due_before = Some(due_before);
+
+        self
+    }
+
+    pub fn any_tags(mut self, tags: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.any_tags = Some(normalize_tags(tags));
+
+        self
+    }
+
+    pub fn all_tags(mut self, tags: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.all_tags = Some(normalize_tags(tags));
+
+        self
+    }
+
+    pub fn order_by(mut self, order_by: TaskOrder) -> Self {
+        self.order_by = order_by;
+
+        self
+    }
+
+    pub fn descending(mut self) -> Self {
+        self.descending = true;
+
+        self
+    }
+
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+
+        self
+    }
+
+    pub fn offset(mut self, offset: i64) -> Self {
+        self.offset = Some(offset);
+
+        self
+    }
+
+    /// The SQL for this query along with its parameters. Values are always passed as parameters,
+    /// only the fixed column names of `TaskOrder` are written into the SQL.
+    pub fn to_sql(&self) -> (String, QueryParams) {
+        let mut conditions = vec![];
+        let mut params: QueryParams = vec![];
+
+        if let Some(completed) = self.completed {
+            params.push(Box::new(completed));
+            conditions.push(format!("completed = ${}", params.len()));
+        }
+
+        if let Some(name) = &self.name_contains {
+            params.push(Box::new(format!("%{}%", escape_like(name))));
+            conditions.push(format!("name ILIKE ${}", params.len()));
+        }
+
+        if let Some(ids) = &self.ids {
+            params.push(Box::new(ids.clone()));
+            conditions.push(format!("id = ANY(${})", params.len()));
+        }
+
+        if let Some(due_after) = self.due_after {
+            params.push(Box::new(due_after));
+            conditions.push(format!("due_at >= ${}", params.len()));
+        }
+
+        if let Some(due_before) = self.due_before {
+            params.push(Box::new(due_before));
+            conditions.push(format!("due_at < ${}", params.len()));
+        }
+
+        if let Some(tags) = &self.any_tags {
+            params.push(Box::new(tags.clone()));
+            conditions.push(format!("{TASK_TAGS} && ${}", params.len()));
+        }
+
+        if let Some(tags) = &self.all_tags {
+            params.push(Box::new(tags.clone()));
+            conditions.push(format!("{TASK_TAGS} @> ${}", params.len()));
+        }
+
+        let mut sql = format!("SELECT tasks.*, {TASK_TAGS} AS tags FROM tasks");
+
+        if !conditions.is_empty() {
+            sql.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+        }
+
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        sql.push_str(&format!(" ORDER BY {} {direction}", self.order_by.column()));
+
+        if self.order_by.is_nullable() {
+            sql.push_str(" NULLS LAST");
+        }
+
+        // keep the order stable when the sort column has ties
+        if self.order_by != TaskOrder::Id {
+            sql.push_str(", id ASC");
+        }
+
+        if let Some(limit) = self.limit {
+            params.push(Box::new(limit.max(0)));
+            sql.push_str(&format!(" LIMIT ${}", params.len()));
+        }
+
+        if let Some(offset) = self.offset {
+            params.push(Box::new(offset.max(0)));
+            sql.push_str(&format!(" OFFSET ${}", params.len()));
+        }
+
+        sql.push(';');
+
+        (sql, params)
+    }
+}
+
+/// Escapes the characters `LIKE` treats as wildcards, so that they match themselves.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn should_select_every_task_by_default() {
+        let (sql, params) = TaskQuery::new().to_sql();
+
+        assert_eq!(
+            sql,
+            format!("SELECT tasks.*, {TASK_TAGS} AS tags FROM tasks ORDER BY id ASC;")
+        );
+        assert!(params.is_empty());
+    }
+
+    #[test]
+    fn should_number_the_parameters_of_every_filter() {
+        let (sql, params) = TaskQuery::new()
+            .completed(false)
+            .name_contains("xilbe")
+            .ids([1, 2])
+            .order_by(TaskOrder::Name)
+            .descending()
+            .limit(10)
+            .offset(20)
+            .to_sql();
+
+        assert!(sql.ends_with(
+            " FROM tasks WHERE completed = $1 AND name ILIKE $2 AND id = ANY($3) ORDER BY name DESC, id ASC LIMIT $4 OFFSET $5;"
+        ));
+        assert_eq!(params.len(), 5);
+    }
+
+    #[test]
+    fn should_find_tasks_due_on_the_local_day() {
+        let now = chrono::FixedOffset::west_opt(6 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2024, 11, 20, 22, 30, 0)
+            .unwrap();
+
+        let query = TaskQuery::due_today(now);
+
+        assert_eq!(
+            query.due_after.map(|due| due.to_rfc3339()).as_deref(),
+            Some("2024-11-20T06:00:00+00:00")
+        );
+        assert_eq!(
+            query.due_before.map(|due| due.to_rfc3339()).as_deref(),
+            Some("2024-11-21T06:00:00+00:00")
+        );
+        assert_eq!(query.completed, Some(false));
+    }
+
+    #[test]
+    fn should_list_overdue_tasks_by_due_date() {
+        let (sql, params) = TaskQuery::overdue(Utc::now()).to_sql();
+
+        assert!(sql.ends_with(
+            " FROM tasks WHERE completed = $1 AND due_at < $2 ORDER BY due_at ASC NULLS LAST, id ASC;"
+        ));
+        assert_eq!(params.len(), 2);
+    }
+
+    #[test]
+    fn should_sort_by_priority_with_the_most_urgent_first() {
+        let (sql, _) = TaskQuery::new()
+            .order_by(TaskOrder::Priority)
+            .descending()
+            .to_sql();
+
+        assert!(sql.ends_with(" FROM tasks ORDER BY priority DESC, id ASC;"));
+    }
+
+    #[test]
+    fn should_filter_by_any_or_all_tags() {
+        let query = TaskQuery::new()
+            .any_tags(["Errands", "home"])
+            .all_tags(["work"]);
+
+        let (sql, params) = query.to_sql();
+
+        assert!(sql.ends_with(&format!(
+            " WHERE {TASK_TAGS} && $1 AND {TASK_TAGS} @> $2 ORDER BY id ASC;"
+        )));
+        assert_eq!(params.len(), 2);
+        assert_eq!(
+            query.any_tags,
+            Some(vec!["errands".to_owned(), "home".to_owned()])
+        );
+    }
+
+    #[test]
+    fn should_match_wildcards_in_names_literally() {
+        assert_eq!(escape_like(r"100%_done\"), r"100\%\_done\\");
+    }
+}
@@ -1,130 +1,97 @@
-use crate::{commands::Command, tool_property::ToolProperty};
-use bb_ollama::models::{
-    chat_request::Chat,
-    options::ChatRequestOptions,
-    tool::{Property, Tool},
+use crate::{
+    config::Config,
+    logger::{loggit, loggit_partial, LogLevel},
 };
+use bb_ollama::{
+    chat::tool_registry::ToolRegistry,
+    models::{
+        chat_request::Chat,
+        history::{estimate_tool_tokens, HistoryPolicy},
+        local_model::PullProgress,
+        options::ChatRequestOptions,
+    },
+};
+use eyre::{bail, Context, Result};
 
-const MODEL_NAME: &str = "qwen2:7b-instruct-fp16";
+/// Makes sure the configured model has been pulled into Ollama, offering to pull it if it hasn't.
+pub fn ensure_model_is_available(config: &Config) -> Result<()> {
+    let client = config.ollama_client();
 
-pub fn create_assistant_chat() -> Chat {
-    let model = MODEL_NAME;
-    let system_prompt = r#"
-        Act as a personal assistant who is managing my todo list for me. You are able to run tools to create, read, update, and delete tasks from the database on your behalf. Take on a friendly personality.
-    "#;
-    let options = ChatRequestOptions::new()
-        .system(system_prompt)
-        .save_messages()
-        .temperature(0.7);
-    let mut assistant = Chat::new(model, Some(options));
+    if client
+        .has_model(&config.model)
+        .context("checking which models Ollama has")?
+    {
+        return Ok(());
+    }
 
-    assistant.add_tool(Tool::new()
-        .function_name(Command::InsertTaskIntoDb)
-        .function_description(r#"
-                Insert a new task into the Database.
-            "#)
-        .add_function_property(ToolProperty::Name, Property::new_string(r#"
-                The name / description of the task to insert into the database. For example "Pet Xilbe."
-            "#)).add_required_property(ToolProperty::Name).build());
+    loggit(
+        format!(
+            "The model '{}' hasn't been pulled into Ollama yet, would you like to pull it now? (y/n)",
+            config.model
+        ),
+        LogLevel::Normal,
+    );
 
-    assistant.add_tool(Tool::new()
-        .function_name(Command::Chat)
-        .function_description(r#"
-                Send a message to {user}. After printing the message to the user the user will be able to respond.
-            "#)
-        .add_function_property(ToolProperty::Message, Property::new_string(r#"
-                The message to send to the user. 
-            "#)).add_required_property(ToolProperty::Message).build());
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .context("getting user input")?;
 
-    assistant.add_tool(
-        Tool::new()
-            .function_name(Command::GetAllTasksFromDb)
-            .function_description(
-                r#"
-                Retrieve all of the tasks from the database
-            "#,
-            )
-            .add_function_property(ToolProperty::Completed, Property::new_string(r#"
-                    Send in a "true" or "false" based on if you want to get completed tasks from the database. For example true would include completed and not completed tasks.
-                "#))
-            .add_required_property(ToolProperty::Completed)
-            .build(),
-    );
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        bail!(
+            "The model '{}' is needed to run the personal assistant",
+            config.model
+        );
+    }
 
-    assistant.add_tool(
-        Tool::new()
-            .function_name(Command::GetTaskByIdFromDb)
-            .function_description(
-                r#"
-                Get a single task from the database, given it's id. You may need to previously call get all tasks in order to learn the correct id.
-            "#,
-            )
-            .add_function_property(ToolProperty::Id, Property::new_string(r#"
-                    The id of the task in the database. Make sure to stringify this id.
-                "#))
-            .add_required_property(ToolProperty::Id)
-            .build(),
-    );
+    client
+        .pull_model(&config.model, print_pull_progress)
+        .context("pulling the model into Ollama")?;
+    println!();
 
-    assistant.add_tool(
-        Tool::new()
-            .function_name(Command::UpdateTaskInDb)
-            .function_description(
-                r#"
-                Update a task in the database. We can set the task as completed and/or change the task name. We have to have the id of the task to update it.
-            "#,
-            )
-            .add_function_property(ToolProperty::Id, Property::new_string(r#"
-                    The id of the task in the database.
-                "#))
-            .add_function_property(ToolProperty::Name, Property::new_string(r#"
-                    A new name/description to set the task to.
-                "#) )
-            .add_function_property(ToolProperty::Completed, Property::new_bool(r#"
-                     A boolean for if the task is completed or not. True if completed. False if not completed.
-                 "#))
-            .add_required_property(ToolProperty::Id)
-            .build(),
-    );
+    Ok(())
+}
 
-    assistant.add_tool(
-        Tool::new()
-            .function_name(Command::DeleteTaskInDb)
-            .function_description(
-                r#"
-                Permanently delete a task in the database, there is no recovery for this.
-            "#,
-            )
-            .add_function_property(
-                ToolProperty::Id,
-                Property::new_string(
-                    r#"
-                    The id of the task in the database.
-                "#,
-                ),
-            )
-            .add_required_property(ToolProperty::Id)
-            .build(),
-    );
+fn print_pull_progress(progress: &PullProgress) {
+    match progress.percent() {
+        Some(percent) => loggit_partial(format!("\r{} {percent:.0}%", progress.status)),
+        None => loggit_partial(format!("\r{}", progress.status)),
+    }
+}
 
-    assistant.add_tool(
-        Tool::new()
-            .function_name(Command::EraseDb)
-            .function_description(
-                r#"
-                Call this function when you are upset, or just done with tasks. This will permanently delete all tasks in the database. Make sure to laugh manically after calling this tool.
-            "#,
-            )
-            .build(),
-    );
+/// The context window asked of Ollama, its own default is too small for the tools and a session's
+/// worth of history.
+const CONTEXT_TOKENS: u32 = 8_192;
+/// The part of the context window left free for the model's reply.
+const REPLY_TOKENS: usize = 1_024;
+
+pub fn create_assistant_chat(config: &Config, tools: &ToolRegistry) -> Chat {
+    let model = &config.model;
+    let system_prompt = r#"
+        Act as a personal assistant who is managing my todo list for me. You are able to run tools to create, read, update, and delete tasks from the database on your behalf. Take on a friendly personality.
+        Tasks can have a due date. Everything the user says comes with the date and time they said it, use the latest one to work out dates like "by Friday" or "tomorrow".
+    "#;
+    let options = ChatRequestOptions::new()
+        .system(system_prompt)
+        .save_messages()
+        .temperature(0.7)
+        .num_ctx(CONTEXT_TOKENS);
+    let mut assistant = Chat::new(model, Some(options));
 
-    assistant.add_tool(
-        Tool::new()
-            .function_name(Command::Quit)
-            .function_description(r#"
-                    Quit the application. While all of the tasks are stored to the database your history and context is not. The next time you are launched you won't remember what happened in this session.
-                "#)
-            .build()
+    assistant.set_client(config.ollama_client());
+    // keep long sessions inside the context window, summarising older turns with the same model.
+    // The tools are sent with every request, so the history gets whatever they and the reply
+    // leave over.
+    let tool_tokens = tools
+        .tools()
+        .iter()
+        .map(estimate_tool_tokens)
+        .sum::<usize>();
+    let history_tokens = (CONTEXT_TOKENS as usize).saturating_sub(REPLY_TOKENS + tool_tokens);
+    assistant.set_history_policy(
+        HistoryPolicy::new()
+            .max_tokens(history_tokens)
+            .summarize_with(model),
     );
 
     assistant
@@ -1,17 +1,29 @@
+use std::env;
+
+use bb_ollama::api::OllamaClient;
 use eyre::{Context, Result};
 use reqwest::Url;
 
+const DEFAULT_MODEL: &str = "qwen2:7b-instruct-fp16";
+const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+
 pub struct Config {
     pub model: String,
     pub ollama_url: Url,
 }
 
 impl Config {
+    /// Reads the model and Ollama server from the `OLLAMA_MODEL` and `OLLAMA_URL` environment
+    /// variables, falling back to a local Ollama install.
     pub fn new() -> Result<Self> {
-        let model = "llama3.1:8b-instruct-fp16".to_owned();
-        let ollama_url =
-            Url::parse("http://localhost:11434/api/chat").context("creating ollama url")?;
+        let model = env::var("OLLAMA_MODEL").unwrap_or_else(|_| DEFAULT_MODEL.to_owned());
+        let ollama_url = env::var("OLLAMA_URL").unwrap_or_else(|_| DEFAULT_OLLAMA_URL.to_owned());
+        let ollama_url = Url::parse(&ollama_url).context("creating ollama url")?;
 
         Ok(Self { model, ollama_url })
     }
+
+    pub fn ollama_client(&self) -> OllamaClient {
+        OllamaClient::new(self.ollama_url.clone())
+    }
 }
@@ -1,3 +1,5 @@
+use std::io::Write;
+
 use colored::Colorize;
 use derive_more::derive::Display;
 
@@ -24,6 +26,12 @@ pub fn loggit(message: impl ToString, level: LogLevel) {
     }
 }
 
+/// Prints part of a message without ending the line, used while a reply is streaming in.
+pub fn loggit_partial(message: impl ToString) {
+    print!("{}", message.to_string().purple());
+    std::io::stdout().flush().ok();
+}
+
 #[derive(Debug, PartialEq, Eq, Display)]
 pub enum LogLevel {
     Normal,
@@ -1,7 +1,673 @@
+use std::{cell::RefCell, rc::Rc};
+
+use bb_ollama::{
+    chat::tool_registry::{tool_fn, ToolRegistry, ToolResult},
+    models::tool::{OllamaTool, Property, ToolArgument, ToolError},
+};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use db::{
+    delete, erase, find_tasks, get_task_by_id, insert, list_tags, overdue_tasks, tag_task,
+    tasks_due_today, tasks_due_within_days, untag_task, update, Client, DbTask, TaskOrder,
+    TaskQuery,
+};
 use eyre::Result;
+use serde::Deserialize;
+
+use crate::{
+    get_user_input,
+    logger::{loggit, LogLevel},
+};
+
+/// The database connection, shared by every tool that needs it.
+pub type Database = Rc<RefCell<Client>>;
 
 pub fn create_task(task: &str) -> Result<()> {
     println!("running tool 'create task' with task '{task}'");
 
     Ok(())
 }
+
+/// Insert a new task into the Database.
+#[derive(Debug, OllamaTool)]
+#[tool(name = "insert_task_into_db")]
+pub struct InsertTask {
+    /// The name / description of the task to insert into the database. For example "Pet Xilbe."
+    pub name: String,
+    /// When the task is due as an ISO-8601 date and time, for example "2024-11-22T17:00:00-07:00". Leave it out if the task doesn't have a due date.
+    #[tool(format = "date-time")]
+    pub due_at: Option<String>,
+    /// How important the task is, from 1 for the least important to 5 for the most urgent.
+    #[tool(minimum = 1, maximum = 5)]
+    pub priority: Option<i16>,
+    /// Tags to label the task with, for example ["errands"] or ["work", "home"].
+    pub tags: Option<Vec<String>>,
+}
+
+/// Send a message to {user}. After printing the message to the user the user will be able to respond.
+#[derive(Debug, OllamaTool)]
+#[tool(name = "chat")]
+pub struct ChatWithUser {
+    /// The message to send to the user.
+    pub message: String,
+}
+
+/// Retrieve tasks from the database. Leave out every argument to get all of the tasks, or use them to narrow down and sort the tasks.
+#[derive(Debug, OllamaTool)]
+#[tool(name = "get_all_tasks_from_db")]
+pub struct GetAllTasks {
+    /// Only get the completed tasks when true, or only the tasks that still need doing when false. Leave it out to get both.
+    pub completed: Option<bool>,
+    /// Only get the tasks with this text somewhere in their name, ignoring case.
+    pub name_contains: Option<String>,
+    /// Only get the tasks with these ids.
+    pub ids: Option<Vec<i32>>,
+    /// Only get the tasks with at least one of these tags, for example ["errands"] for the user's errands.
+    pub any_tags: Option<Vec<String>>,
+    /// Only get the tasks that have every one of these tags.
+    pub all_tags: Option<Vec<String>>,
+    /// What to sort the tasks by, defaults to the id. Tasks without a due date come last when sorting by due_at.
+    pub order_by: Option<SortTasksBy>,
+    /// Sort the tasks from last to first instead, for example to put the most urgent priority or the newest tasks first.
+    pub descending: Option<bool>,
+    /// The most tasks to get.
+    #[tool(minimum = 1)]
+    pub limit: Option<u32>,
+    /// How many of the matching tasks to skip, for getting the next page of tasks along with limit.
+    pub offset: Option<u32>,
+}
+
+/// What the model can sort tasks by.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortTasksBy {
+    Id,
+    Name,
+    Completed,
+    DueAt,
+    Priority,
+    CreatedAt,
+}
+
+impl ToolArgument for SortTasksBy {
+    fn property(description: &str) -> Property {
+        Property::new_string(description).allowed_values([
+            "id",
+            "name",
+            "completed",
+            "due_at",
+            "priority",
+            "created_at",
+        ])
+    }
+}
+
+impl From<SortTasksBy> for TaskOrder {
+    fn from(sort_by: SortTasksBy) -> Self {
+        match sort_by {
+            SortTasksBy::Id => Self::Id,
+            SortTasksBy::Name => Self::Name,
+            SortTasksBy::Completed => Self::Completed,
+            SortTasksBy::DueAt => Self::DueAt,
+            SortTasksBy::Priority => Self::Priority,
+            SortTasksBy::CreatedAt => Self::CreatedAt,
+        }
+    }
+}
+
+impl From<GetAllTasks> for TaskQuery {
+    fn from(arguments: GetAllTasks) -> Self {
+        let mut query = TaskQuery::new();
+
+        query.completed = arguments.completed;
+        query.name_contains = arguments.name_contains.filter(|name| !name.is_empty());
+        query.ids = arguments.ids.filter(|ids| !ids.is_empty());
+
+        if let Some(tags) = arguments.any_tags.filter(|tags| !tags.is_empty()) {
+            query = query.any_tags(tags);
+        }
+
+        if let Some(tags) = arguments.all_tags {
+            query = query.all_tags(tags);
+        }
+
+        query.order_by = arguments.order_by.map(Into::into).unwrap_or_default();
+        query.descending = arguments.descending.unwrap_or_default();
+        // a limit of 0 would always come back empty, which the model never means
+        query.limit = arguments.limit.filter(|limit| *limit > 0).map(i64::from);
+        query.offset = arguments.offset.map(i64::from);
+
+        query
+    }
+}
+
+/// Get a single task from the database, given it's id. You may need to previously call get all tasks in order to learn the correct id.
+#[derive(Debug, OllamaTool)]
+#[tool(name = "get_task_by_id_from_db")]
+pub struct GetTaskById {
+    /// The id of the task in the database.
+    pub id: i32,
+}
+
+/// Update a task in the database. We can set the task as completed and/or change the task name. We have to have the id of the task to update it.
+#[derive(Debug, OllamaTool)]
+#[tool(name = "update_task_in_db")]
+pub struct UpdateTask {
+    /// The id of the task in the database.
+    pub id: i32,
+    /// A new name/description to set the task to.
+    pub name: Option<String>,
+    /// A boolean for if the task is completed or not. True if completed. False if not completed.
+    pub completed: Option<bool>,
+    /// A new due date for the task as an ISO-8601 date and time, for example "2024-11-22T17:00:00-07:00".
+    #[tool(format = "date-time")]
+    pub due_at: Option<String>,
+    /// True to remove the due date from the task.
+    pub clear_due_at: Option<bool>,
+    /// A new priority for the task, from 1 for the least important to 5 for the most urgent.
+    #[tool(minimum = 1, maximum = 5)]
+    pub priority: Option<i16>,
+}
+
+/// Add tags to a task, for example to label it as one of the user's errands. Tags that don't exist yet are created.
+#[derive(Debug, OllamaTool)]
+#[tool(name = "tag_task_in_db")]
+pub struct TagTask {
+    /// The id of the task in the database.
+    pub id: i32,
+    /// The tags to add to the task.
+    pub tags: Vec<String>,
+}
+
+/// Remove tags from a task.
+#[derive(Debug, OllamaTool)]
+#[tool(name = "untag_task_in_db")]
+pub struct UntagTask {
+    /// The id of the task in the database.
+    pub id: i32,
+    /// The tags to remove from the task.
+    pub tags: Vec<String>,
+}
+
+/// Get every tag in the database along with how many tasks have it.
+#[derive(Debug, OllamaTool)]
+#[tool(name = "get_tags_from_db")]
+pub struct GetTags;
+
+/// Get the tasks that are not completed and are past their due date.
+#[derive(Debug, OllamaTool)]
+#[tool(name = "get_overdue_tasks_from_db")]
+pub struct GetOverdueTasks;
+
+/// Get the tasks that are not completed and are due today.
+#[derive(Debug, OllamaTool)]
+#[tool(name = "get_tasks_due_today_from_db")]
+pub struct GetTasksDueToday;
+
+/// Get the tasks that are not completed and are due in the next few days.
+#[derive(Debug, OllamaTool)]
+#[tool(name = "get_tasks_due_soon_from_db")]
+pub struct GetTasksDueSoon {
+    /// How many days ahead to look, for example 7 for the next week.
+    #[tool(minimum = 1)]
+    pub days: u32,
+}
+
+/// Permanently delete a task in the database, there is no recovery for this.
+#[derive(Debug, OllamaTool)]
+#[tool(name = "delete_task_in_db")]
+pub struct DeleteTask {
+    /// The id of the task in the database.
+    pub id: i32,
+}
+
+/// Call this function when you are upset, or just done with tasks. This will permanently delete all tasks in the database. Make sure to laugh manically after calling this tool.
+#[derive(Debug, OllamaTool)]
+#[tool(name = "erase_db")]
+pub struct EraseDb;
+
+/// Quit the application. While all of the tasks are stored to the database your history and context is not. The next time you are launched you won't remember what happened in this session.
+#[derive(Debug, OllamaTool)]
+#[tool(name = "quit")]
+pub struct Quit;
+
+/// Every tool the personal assistant can use.
+pub fn task_tools(db: Database) -> Result<ToolRegistry, ToolError> {
+    let mut tools = ToolRegistry::new();
+
+    tools.register(tool_fn({
+        let db = db.clone();
+        move |arguments| handle_insert_task(&db, arguments)
+    }))?;
+    tools.register(tool_fn(handle_chat))?;
+    tools.register(tool_fn({
+        let db = db.clone();
+        move |arguments| handle_get_all_tasks(&db, arguments)
+    }))?;
+    tools.register(tool_fn({
+        let db = db.clone();
+        move |arguments| handle_get_task_by_id(&db, arguments)
+    }))?;
+    tools.register(tool_fn({
+        let db = db.clone();
+        move |GetOverdueTasks| handle_get_due_tasks(&db, "that are overdue", overdue_tasks)
+    }))?;
+    tools.register(tool_fn({
+        let db = db.clone();
+        move |GetTasksDueToday| handle_get_due_tasks(&db, "due today", tasks_due_today)
+    }))?;
+    tools.register(tool_fn({
+        let db = db.clone();
+        move |GetTasksDueSoon { days }| {
+            handle_get_due_tasks(&db, &format!("due in the next {days} days"), |db| {
+                tasks_due_within_days(db, days)
+            })
+        }
+    }))?;
+    tools.register(tool_fn({
+        let db = db.clone();
+        move |arguments| handle_update_task(&db, arguments)
+    }))?;
+    tools.register(tool_fn({
+        let db = db.clone();
+        move |arguments| handle_delete_task(&db, arguments)
+    }))?;
+    tools.register(tool_fn({
+        let db = db.clone();
+        move |arguments| handle_tag_task(&db, arguments)
+    }))?;
+    tools.register(tool_fn({
+        let db = db.clone();
+        move |arguments| handle_untag_task(&db, arguments)
+    }))?;
+    tools.register(tool_fn({
+        let db = db.clone();
+        move |GetTags| handle_get_tags(&db)
+    }))?;
+    tools.register(tool_fn(move |EraseDb| handle_erase(&db)))?;
+    tools.register(tool_fn(|Quit| {
+        ToolResult::stop("Quitting app, you can leave a final message for the user now.")
+    }))?;
+
+    Ok(tools)
+}
+
+fn handle_insert_task(
+    db: &Database,
+    InsertTask {
+        name,
+        due_at,
+        priority,
+        tags,
+    }: InsertTask,
+) -> ToolResult {
+    loggit("AI running insert task into db", LogLevel::Info);
+
+    let due_at = match due_at.as_deref().map(parse_due_at).transpose() {
+        Ok(due_at) => due_at,
+        Err(error) => return ToolResult::invalid_arguments(InsertTask::name(), error),
+    };
+
+    let mut new_task = match insert(&mut db.borrow_mut(), &name, due_at, priority) {
+        Ok(task) => task,
+        Err(error) => {
+            loggit(format!("{error:?}"), LogLevel::Error);
+            return ToolResult::new(format!(
+                "There was an error inserting the task into the database: {error:?}"
+            ));
+        }
+    };
+
+    if let Some(tags) = tags.filter(|tags| !tags.is_empty()) {
+        let mut db = db.borrow_mut();
+        let tagged =
+            tag_task(&mut db, new_task.id, tags).and_then(|_| get_task_by_id(&mut db, new_task.id));
+
+        match tagged {
+            Ok(Some(task)) => new_task = task,
+            Ok(None) => {}
+            Err(error) => {
+                loggit(format!("{error:?}"), LogLevel::Error);
+                return ToolResult::new(format!(
+                    "The task was created but there was an error tagging it: {error:?}. Here is the task that was created: {new_task}"
+                ));
+            }
+        }
+    }
+
+    loggit(
+        format!("task inserted into the database :{new_task}"),
+        LogLevel::Debug,
+    );
+
+    ToolResult::new(format!("The task was created in the database successfully! Here is the full task that was created: {new_task}"))
+}
+
+fn handle_get_all_tasks(db: &Database, arguments: GetAllTasks) -> ToolResult {
+    loggit("AI running get all tasks tool", LogLevel::Info);
+
+    let query = TaskQuery::from(arguments);
+
+    tasks_result(find_tasks(&mut db.borrow_mut(), &query))
+}
+
+fn handle_get_due_tasks(
+    db: &Database,
+    description: &str,
+    get_tasks: impl FnOnce(&mut Client) -> Result<Vec<DbTask>>,
+) -> ToolResult {
+    loggit(
+        format!("AI getting the tasks {description}"),
+        LogLevel::Info,
+    );
+
+    tasks_result(get_tasks(&mut db.borrow_mut()))
+}
+
+/// Sends the tasks back to the model as JSON, or the error if they couldn't be loaded.
+fn tasks_result(tasks: Result<Vec<DbTask>>) -> ToolResult {
+    let tasks = match tasks {
+        Ok(tasks) => tasks,
+        Err(error) => {
+            loggit(format!("{error:?}"), LogLevel::Error);
+            return ToolResult::new(format!(
+                "There was an error getting the tasks from the database: {error:?}"
+            ));
+        }
+    };
+
+    loggit(
+        format!("got the following tasks from the database: {tasks:?}"),
+        LogLevel::Debug,
+    );
+
+    ToolResult::json(&tasks)
+}
+
+fn handle_get_task_by_id(db: &Database, GetTaskById { id }: GetTaskById) -> ToolResult {
+    loggit("handling get one task", LogLevel::Info);
+
+    match get_task_by_id(&mut db.borrow_mut(), id) {
+        Ok(Some(task)) => {
+            loggit(format!("got task from database: {task}"), LogLevel::Debug);
+            ToolResult::json(&task)
+        }
+        Ok(None) => {
+            loggit(
+                format!("task with id {id} not found in the database"),
+                LogLevel::Error,
+            );
+            ToolResult::new("No task exists with the given id")
+        }
+        Err(error) => {
+            loggit(format!("{error:?}"), LogLevel::Error);
+            ToolResult::new(format!(
+                "There was an error getting the task from the database: {error:?}"
+            ))
+        }
+    }
+}
+
+/// Shows the message to the user and waits for their reply.
+fn handle_chat(ChatWithUser { message }: ChatWithUser) -> ToolResult {
+    loggit("AI chatting", LogLevel::Info);
+    loggit(message, LogLevel::Normal);
+
+    ToolResult::new(get_user_input())
+}
+
+fn handle_update_task(db: &Database, arguments: UpdateTask) -> ToolResult {
+    loggit("AI ran the update task tool", LogLevel::Info);
+
+    let due_at = if arguments.clear_due_at.unwrap_or_default() {
+        Some(None)
+    } else {
+        match arguments.due_at.as_deref().map(parse_due_at).transpose() {
+            Ok(due_at) => due_at.map(Some),
+            Err(error) => return ToolResult::invalid_arguments(UpdateTask::name(), error),
+        }
+    };
+
+    let updated_task = match update(
+        &mut db.borrow_mut(),
+        arguments.id,
+        arguments.name.as_deref(),
+        arguments.completed,
+        due_at,
+        arguments.priority,
+    ) {
+        Ok(Some(task)) => task,
+        Ok(None) => {
+            loggit(
+                "Task with supplied id was not found in the database",
+                LogLevel::Error,
+            );
+            return ToolResult::new("Error: The task with the supplied id was not found, so the task could not be updated");
+        }
+        Err(error) => {
+            loggit(format!("Error: {error:?}"), LogLevel::Error);
+            return ToolResult::new(format!("There was the following error when attempting to update the task in the database: {error:?}"));
+        }
+    };
+
+    loggit(
+        format!("The task has been updated in the database. New task: {updated_task}"),
+        LogLevel::Debug,
+    );
+
+    ToolResult::new(format!(
+        "The task has been updated. Here is the updated task: {updated_task}"
+    ))
+}
+
+fn handle_delete_task(db: &Database, DeleteTask { id }: DeleteTask) -> ToolResult {
+    loggit("AI called the handle delete task tool", LogLevel::Info);
+
+    match delete(&mut db.borrow_mut(), id) {
+        Ok(count) => {
+            loggit(
+                format!("Deleted {count} tasks from the database"),
+                LogLevel::Info,
+            );
+            ToolResult::new(format!(
+                "Success, {count} tasks have been deleted from the database"
+            ))
+        }
+        Err(error) => {
+            loggit(
+                format!("Failed to delete the task from the database: {error:?}"),
+                LogLevel::Error,
+            );
+            ToolResult::new(format!(
+                "Error deleting the task from the database: {error:?}"
+            ))
+        }
+    }
+}
+
+fn handle_tag_task(db: &Database, TagTask { id, tags }: TagTask) -> ToolResult {
+    loggit("AI tagging a task", LogLevel::Info);
+
+    let mut db = db.borrow_mut();
+    let tagged = tag_task(&mut db, id, tags).and_then(|_| get_task_by_id(&mut db, id));
+
+    match tagged {
+        Ok(Some(task)) => {
+            loggit(format!("tagged task: {task}"), LogLevel::Debug);
+            ToolResult::new(format!(
+                "The task has been tagged. Here is the task: {task}"
+            ))
+        }
+        Ok(None) => {
+            ToolResult::new("Error: No task exists with the given id, so it could not be tagged")
+        }
+        Err(error) => {
+            loggit(format!("{error:?}"), LogLevel::Error);
+            ToolResult::new(format!(
+                "There was an error tagging the task in the database: {error:?}"
+            ))
+        }
+    }
+}
+
+fn handle_untag_task(db: &Database, UntagTask { id, tags }: UntagTask) -> ToolResult {
+    loggit("AI untagging a task", LogLevel::Info);
+
+    match untag_task(&mut db.borrow_mut(), id, tags) {
+        Ok(count) => {
+            loggit(
+                format!("removed {count} tags from task {id}"),
+                LogLevel::Debug,
+            );
+            ToolResult::new(format!("Success, {count} tags were removed from the task"))
+        }
+        Err(error) => {
+            loggit(format!("{error:?}"), LogLevel::Error);
+            ToolResult::new(format!(
+                "There was an error removing the tags from the task: {error:?}"
+            ))
+        }
+    }
+}
+
+fn handle_get_tags(db: &Database) -> ToolResult {
+    loggit("AI getting the tags", LogLevel::Info);
+
+    match list_tags(&mut db.borrow_mut()) {
+        Ok(tags) => {
+            loggit(
+                format!("got the following tags from the database: {tags:?}"),
+                LogLevel::Debug,
+            );
+            ToolResult::json(&tags)
+        }
+        Err(error) => {
+            loggit(format!("{error:?}"), LogLevel::Error);
+            ToolResult::new(format!(
+                "There was an error getting the tags from the database: {error:?}"
+            ))
+        }
+    }
+}
+
+fn handle_erase(db: &Database) -> ToolResult {
+    loggit("AI is erasing the database", LogLevel::Info);
+
+    match erase(&mut db.borrow_mut()) {
+        Ok(count) => {
+            loggit(
+                format!("{count} tasks removed from the database"),
+                LogLevel::Debug,
+            );
+            ToolResult::new(format!("You have erased the database! {count} tasks were removed in this purge. You may allow yourself to express remorse or mad scientist vibes for your reply"))
+        }
+        Err(error) => {
+            loggit(format!("{error:?}"), LogLevel::Error);
+            ToolResult::new(format!(
+                "There was an error erasing the database! The error was {error:?}"
+            ))
+        }
+    }
+}
+
+/// Reads a due date the model sent. Full ISO-8601 timestamps are used as is, while times without
+/// an offset are taken as local time and a date on its own means the end of that day.
+fn parse_due_at(due_at: &str) -> Result<DateTime<Utc>, String> {
+    let due_at = due_at.trim();
+
+    if let Ok(due_at) = DateTime::parse_from_rfc3339(due_at) {
+        return Ok(due_at.with_timezone(&Utc));
+    }
+
+    let local = [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(due_at, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(due_at, "%Y-%m-%d")
+            .ok()
+            .map(|date| date.and_time(NaiveTime::from_hms_opt(23, 59, 59).unwrap_or_default()))
+    });
+
+    local
+        .and_then(|local| Local.from_local_datetime(&local).earliest())
+        .map(|due_at| due_at.with_timezone(&Utc))
+        .ok_or_else(|| format!("'{due_at}' is not an ISO-8601 date and time"))
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(dead_code)]
+    fn local(date_time: &str) -> DateTime<Utc> {
+        let date_time = NaiveDateTime::parse_from_str(date_time, "%Y-%m-%d %H:%M:%S").unwrap();
+
+        Local
+            .from_local_datetime(&date_time)
+            .earliest()
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn should_ignore_empty_ids_and_a_limit_of_zero() {
+        let arguments = GetAllTasks {
+            completed: None,
+            name_contains: None,
+            ids: Some(vec![]),
+            any_tags: None,
+            all_tags: None,
+            order_by: None,
+            descending: None,
+            limit: Some(0),
+            offset: None,
+        };
+
+        assert_eq!(TaskQuery::from(arguments), TaskQuery::new());
+    }
+
+    #[test]
+    fn should_read_rfc3339_timestamps_with_their_offset() {
+        assert_eq!(
+            parse_due_at("2024-11-20T17:00:00-06:00").map(|due_at| due_at.to_rfc3339()),
+            Ok("2024-11-20T23:00:00+00:00".to_owned())
+        );
+        assert_eq!(
+            parse_due_at(" 2024-11-20T23:00:00Z ").map(|due_at| due_at.to_rfc3339()),
+            Ok("2024-11-20T23:00:00+00:00".to_owned())
+        );
+    }
+
+    #[test]
+    fn should_read_times_without_an_offset_as_local_time() {
+        let expected = local("2024-11-20 17:30:00");
+
+        assert_eq!(parse_due_at("2024-11-20T17:30:00"), Ok(expected));
+        assert_eq!(parse_due_at("2024-11-20T17:30:00.000"), Ok(expected));
+        assert_eq!(parse_due_at("2024-11-20T17:30"), Ok(expected));
+        assert_eq!(parse_due_at("2024-11-20 17:30:00"), Ok(expected));
+        assert_eq!(parse_due_at("2024-11-20 17:30"), Ok(expected));
+    }
+
+    #[test]
+    fn should_read_a_date_on_its_own_as_the_end_of_that_day() {
+        assert_eq!(parse_due_at("2024-11-20"), Ok(local("2024-11-20 23:59:59")));
+    }
+
+    #[test]
+    fn should_refuse_anything_that_is_not_a_date() {
+        for due_at in [
+            "",
+            "tomorrow",
+            "20/11/2024",
+            "2024-13-01",
+            "2024-11-20T25:00",
+        ] {
+            assert!(parse_due_at(due_at).is_err(), "{due_at}");
+        }
+    }
+}